@@ -1,7 +1,7 @@
 use chrono::{ DateTime, Utc };
 use reqwest::Method;
 use serde::Deserialize;
-use snafu::{ ensure, ResultExt };
+use snafu::ResultExt;
 
 use crate::{ error, util, Alpaca, Result };
 
@@ -105,9 +105,7 @@ impl Account {
    /// let account = Account::get(&alpaca).await.unwrap();
    /// ```
    pub async fn get(alpaca: &Alpaca) -> Result<Account> {
-      let response = alpaca.request(Method::GET, "v2/account")?
-         .send().await.context(error::RequestFailed)?;
-      ensure!(response.status().is_success(), error::InvalidCredentials);
+      let response = alpaca.call(alpaca.request(Method::GET, "v2/account")?).await?;
 
       Ok(response.json::<Account>().await.context(error::BadData)?)
    }
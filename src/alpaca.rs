@@ -1,12 +1,26 @@
-use reqwest::{ Client, Method, RequestBuilder, Url };
+use reqwest::{ Certificate, Client, Method, Proxy, Request, RequestBuilder, Response, Url };
 use serde::Serialize;
-use snafu::ResultExt;
+use snafu::{ ensure, ResultExt };
 use std::env;
+use std::sync::{ Arc, Mutex };
+use std::time::Duration;
+use tokio::time::delay_for;
 
-use crate::{error, Result};
+use crate::{ error, retry, RateLimiter, RateLimitStatus, Result, RetryPolicy };
+use crate::rate_limit::{ Limiter, Priority };
 
-const LIVE_API: &'static str = "https://api.alpaca.markets";
-const PAPER_API: &'static str = "https://paper-api.alpaca.markets";
+const LIVE_API: &str = "https://api.alpaca.markets";
+const LIVE_STREAM: &str = "wss://api.alpaca.markets/stream";
+const PAPER_API: &str = "https://paper-api.alpaca.markets";
+const PAPER_STREAM: &str = "wss://paper-api.alpaca.markets/stream";
+
+const ENV_KEY_ID: &str = "APCA_API_KEY_ID";
+const ENV_SECRET_KEY: &str = "APCA_API_SECRET_KEY";
+const ENV_BASE_URL: &str = "APCA_API_BASE_URL";
+
+const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+const DEFAULT_USER_AGENT: &str = concat!("alpaca-finance/", env!("CARGO_PKG_VERSION"));
 
 
 #[derive(Debug, Serialize)]
@@ -21,50 +35,237 @@ enum ActionMessage {
    Authenticate(Authenticate),
 }
 
-/// Alpaca contextual information that needs to be supplied to all calls.
-pub struct Alpaca {
+/// Where calls to Alpaca are sent.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Endpoint {
+   /// The live trading API
+   Live,
+
+   /// The paper trading API
+   Paper,
+
+   /// Any other host - such as a mock server when testing
+   Custom {
+      /// The base URL for REST calls
+      rest: String,
+
+      /// The URL for the websocket stream
+      stream: String
+   }
+}
+impl Endpoint {
+   /// A custom endpoint where the websocket stream is at `/stream` on the same host as the REST calls
+   ///
+   /// # Example
+   ///
+   /// To send all calls to a local mock server:
+   ///
+   /// ``` no run
+   /// let endpoint = Endpoint::custom("http://127.0.0.1:1234");
+   /// ```
+   pub fn custom(rest_url: &str) -> Endpoint {
+      let rest = rest_url.trim_end_matches('/').to_string();
+      let stream = format!("{}/stream", rest.replacen("http", "ws", 1));
+      Endpoint::Custom { rest, stream }
+   }
+
+   /// Works out the endpoint from a base URL - using Live or Paper if it is one of theirs
+   fn from_url(base_url: &str) -> Endpoint {
+      match base_url.trim_end_matches('/') {
+         LIVE_API => Endpoint::Live,
+         PAPER_API => Endpoint::Paper,
+         _ => Endpoint::custom(base_url)
+      }
+   }
+
+   /// The base URL for REST calls
+   pub fn rest_url(&self) -> &str {
+      match self {
+         Endpoint::Live => LIVE_API,
+         Endpoint::Paper => PAPER_API,
+         Endpoint::Custom { rest, .. } => rest
+      }
+   }
+
+   /// The URL for the websocket stream
+   pub fn stream_url(&self) -> &str {
+      match self {
+         Endpoint::Live => LIVE_STREAM,
+         Endpoint::Paper => PAPER_STREAM,
+         Endpoint::Custom { stream, .. } => stream
+      }
+   }
+}
+
+/// Builds up an Alpaca context, including the HTTP client that is shared by every call made with it.
+///
+/// # Example
+///
+/// To get the alpaca context for the paper account, going through a proxy:
+///
+/// ``` no run
+/// let alpaca = AlpacaBuilder::paper("KEY_ID", "SECRET")
+///    .connect_timeout(Duration::from_secs(2))
+///    .proxy("http://proxy.internal:8080")
+///    .build().await.unwrap();
+/// ```
+#[derive(Debug)]
+pub struct AlpacaBuilder {
+   endpoint: Endpoint,
    api_key: String,
    api_secret: String,
-   host: String
+   connect_timeout: Duration,
+   timeout: Duration,
+   user_agent: String,
+   proxy: Option<String>,
+   root_certificates: Vec<Vec<u8>>,
+   retry_policy: RetryPolicy,
+   rate_limiter: Option<RateLimiter>
 }
-impl Alpaca {
-   /// Builds an alpaca object for either live or paper (sandbox) access
-   async fn build(live: bool, api_key_id: &str, api_secret_key: &str) -> Result<Alpaca> {
-      let host = if live { LIVE_API } else { PAPER_API };
-      let alpaca = Alpaca {
+impl AlpacaBuilder {
+   /// Starts building an object for interacting with the LIVE API
+   pub fn live(api_key_id: &str, api_secret_key: &str) -> AlpacaBuilder { AlpacaBuilder::new(api_key_id, api_secret_key, Endpoint::Live) }
+
+   /// Starts building an object for interacting with the PAPER API
+   pub fn paper(api_key_id: &str, api_secret_key: &str) -> AlpacaBuilder { AlpacaBuilder::new(api_key_id, api_secret_key, Endpoint::Paper) }
+
+   /// Starts building an object for interacting with the API at the endpoint
+   pub fn new(api_key_id: &str, api_secret_key: &str, endpoint: Endpoint) -> AlpacaBuilder {
+      AlpacaBuilder {
+         endpoint,
          api_key: api_key_id.to_string(),
          api_secret: api_secret_key.to_string(),
-         host: env::var("TEST_URL").unwrap_or(host.to_string()) // default to a unit testing URL first
+         connect_timeout: DEFAULT_CONNECT_TIMEOUT,
+         timeout: DEFAULT_TIMEOUT,
+         user_agent: DEFAULT_USER_AGENT.to_string(),
+         proxy: None,
+         root_certificates: vec![],
+         retry_policy: RetryPolicy::default(),
+         rate_limiter: None
+      }
+   }
+
+   /// Starts building an object from the standard Alpaca environment variables:
+   ///  * `APCA_API_KEY_ID` - the key ID
+   ///  * `APCA_API_SECRET_KEY` - the secret key
+   ///  * `APCA_API_BASE_URL` - optional, the base URL of the API.  Defaults to the PAPER API.
+   pub fn from_env() -> Result<AlpacaBuilder> {
+      let api_key_id = env::var(ENV_KEY_ID).context(error::MissingEnvironment { name: ENV_KEY_ID })?;
+      let api_secret_key = env::var(ENV_SECRET_KEY).context(error::MissingEnvironment { name: ENV_SECRET_KEY })?;
+      let endpoint = env::var(ENV_BASE_URL).map(|url| Endpoint::from_url(&url)).unwrap_or(Endpoint::Paper);
+
+      Ok(AlpacaBuilder::new(&api_key_id, &api_secret_key, endpoint))
+   }
+
+   /// Sets how long to wait for a connection to Alpaca - defaults to 10 seconds
+   pub fn connect_timeout(mut self, connect_timeout: Duration) -> AlpacaBuilder {
+      self.connect_timeout = connect_timeout;
+      self
+   }
+
+   /// Sets how long to wait for each call, from sending it until the whole response has been read - defaults
+   /// to 30 seconds
+   pub fn timeout(mut self, timeout: Duration) -> AlpacaBuilder {
+      self.timeout = timeout;
+      self
+   }
+
+   /// Sets the user agent sent with each call
+   pub fn user_agent(mut self, user_agent: &str) -> AlpacaBuilder {
+      self.user_agent = user_agent.to_string();
+      self
+   }
+
+   /// Sends all calls through a proxy
+   pub fn proxy(mut self, proxy_url: &str) -> AlpacaBuilder {
+      self.proxy = Some(proxy_url.to_string());
+      self
+   }
+
+   /// Trusts an extra root certificate (PEM encoded) - for example the one used by a TLS intercepting proxy
+   pub fn root_certificate(mut self, pem: &[u8]) -> AlpacaBuilder {
+      self.root_certificates.push(pem.to_vec());
+      self
+   }
+
+   /// Sets how calls are retried when Alpaca has a temporary problem - defaults to 3 retries
+   pub fn retry_policy(mut self, retry_policy: RetryPolicy) -> AlpacaBuilder {
+      self.retry_policy = retry_policy;
+      self
+   }
+
+   /// Limits how fast calls are sent, queueing them rather than going over Alpaca's rate limit - by default
+   /// there is no client side limit
+   pub fn rate_limiter(mut self, rate_limiter: RateLimiter) -> AlpacaBuilder {
+      self.rate_limiter = Some(rate_limiter);
+      self
+   }
+
+   /// Builds the alpaca object.  Fails if the client settings are invalid or the credentials are not accepted.
+   pub async fn build(self) -> Result<Alpaca> {
+      let mut client = Client::builder()
+         .connect_timeout(self.connect_timeout)
+         .timeout(self.timeout)
+         .user_agent(self.user_agent.as_str());
+      if let Some(proxy_url) = &self.proxy {
+         client = client.proxy(Proxy::all(proxy_url.as_str()).context(error::ClientConfig)?);
+      }
+      for pem in &self.root_certificates {
+         client = client.add_root_certificate(Certificate::from_pem(pem).context(error::ClientConfig)?);
+      }
+
+      // join paths onto the base URL as a directory, so that any path it has is kept
+      let base_url = format!("{}/", self.endpoint.rest_url().trim_end_matches('/'));
+      let alpaca = Alpaca {
+         api_key: self.api_key,
+         api_secret: self.api_secret,
+         client: client.build().context(error::ClientConfig)?,
+         base_url: Url::parse(&base_url).context(error::InvalidEndpoint { url: base_url.as_str() })?,
+         endpoint: self.endpoint,
+         retry_policy: self.retry_policy,
+         limiter: self.rate_limiter.as_ref().map(|rate_limiter| Arc::new(Limiter::new(rate_limiter))),
+         rate_limit_status: Arc::new(Mutex::new(None))
       };
 
       // perform quick test
-      let response = alpaca.request(Method::GET, "v2/clock")?
-         .send().await.context(error::RequestFailed)?;
-      if response.status().is_success() { return Ok(alpaca) }
-
+      let response = alpaca.send(alpaca.request(Method::GET, "v2/clock")?).await?;
       let status = response.status().as_u16();
       match status {
-         401 => error::InvalidCredentials.fail()?,
-         403 => error::InvalidCredentials.fail()?,
-         _ => error::CallFailed{ url: response.url().to_string(), status }.fail()?
+         200..=299 => Ok(alpaca),
+         401 | 403 => error::InvalidCredentials { status: Some(status) }.fail()?,
+         _ => Err(error::failed(response).await)?
       }
    }
+}
 
-   /// Builds a websocket stream against the configured host
-   /// Handles authentication; errors out if credentials are wrong
-   pub(crate) fn stream(&self) -> (String, String) {
-      // first - update the URL for websockets
-      let mut ws_host = self.host.clone();
-      ws_host.replace_range(..4, "ws");
-      ws_host.push_str("/stream");
+/// Alpaca contextual information that needs to be supplied to all calls.
+///
+/// All calls made with the same context share one HTTP client, so connections are reused between calls.
+/// Cloning the context is cheap - the clones share the client, the rate limiter and the rate limit status.
+#[derive(Clone)]
+pub struct Alpaca {
+   api_key: String,
+   api_secret: String,
+   client: Client,
+   base_url: Url,
+   endpoint: Endpoint,
+   retry_policy: RetryPolicy,
+   limiter: Option<Arc<Limiter>>,
+   rate_limit_status: Arc<Mutex<Option<RateLimitStatus>>>
+}
+impl Alpaca {
+   /// Gets the websocket stream URL for the configured endpoint, and the message that authenticates on it
+   pub(crate) fn stream(&self) -> Result<(String, String)> {
+      let ws_host = self.endpoint.stream_url().to_string();
 
       let authenticate = ActionMessage::Authenticate(Authenticate { key_id: self.api_key.clone(), secret_key: self.api_secret.clone() });
-      let message = serde_json::to_string(&authenticate).context(error::InternalJSON).unwrap();
+      let message = serde_json::to_string(&authenticate).context(error::InternalJSON)?;
 
-      (ws_host, message)
+      Ok((ws_host, message))
    }
 
-   /// Creates an object for interacting with the LIVE API
+   /// Creates an object for interacting with the LIVE API, with the default client settings.  Use
+   /// AlpacaBuilder to change them.
    ///
    /// # Example
    ///
@@ -73,9 +274,10 @@ impl Alpaca {
    /// ``` no run
    /// let alpaca = Alpaca::live("KEY_ID", "SECRET").await.unwrap();
    /// ```
-   pub async fn live(api_key_id: &str, api_secret_key: &str) -> Result<Alpaca> { Alpaca::build(true, api_key_id, api_secret_key).await }
+   pub async fn live(api_key_id: &str, api_secret_key: &str) -> Result<Alpaca> { AlpacaBuilder::live(api_key_id, api_secret_key).build().await }
 
-   /// Creates an object for interacting with the PAPER API
+   /// Creates an object for interacting with the PAPER API, with the default client settings.  Use
+   /// AlpacaBuilder to change them.
    ///
    /// # Example
    ///
@@ -84,16 +286,109 @@ impl Alpaca {
    /// ``` no run
    /// let alpaca = Alpaca::paper("KEY_ID", "SECRET").await.unwrap();
    /// ```
-   pub async fn paper(api_key_id: &str, api_secret_key: &str) -> Result<Alpaca> { Alpaca::build(false, api_key_id, api_secret_key).await }
+   pub async fn paper(api_key_id: &str, api_secret_key: &str) -> Result<Alpaca> { AlpacaBuilder::paper(api_key_id, api_secret_key).build().await }
+
+   /// Creates an object from the standard Alpaca environment variables, with the default client settings.
+   /// See AlpacaBuilder.from_env for the variables used.
+   ///
+   /// # Example
+   ///
+   /// To get the alpaca context for whichever account the environment is set up for
+   ///
+   /// ``` no run
+   /// let alpaca = Alpaca::from_env().await.unwrap();
+   /// ```
+   pub async fn from_env() -> Result<Alpaca> { AlpacaBuilder::from_env()?.build().await }
+
+   /// Where the calls made with this context are sent
+   pub fn endpoint(&self) -> &Endpoint { &self.endpoint }
+
+   /// Where the API key stands against Alpaca's rate limit, as of the last call that reported it.  None until
+   /// a call has been made.
+   ///
+   /// # Example
+   ///
+   /// To see how many calls are left before being rate limited:
+   ///
+   /// ``` no run
+   /// if let Some(status) = alpaca.rate_limit_status() {
+   ///    println!("{} of {} calls left until {}", status.remaining, status.limit, status.reset);
+   /// }
+   /// ```
+   pub fn rate_limit_status(&self) -> Option<RateLimitStatus> { *self.rate_limit_status.lock().unwrap() }
 
    /// Internal helper to build up a request to Alpaca with credentials set
    pub(crate) fn request(&self, method: Method, path: &str) -> Result<RequestBuilder> {
-      let url = Url::parse(&self.host).context(error::InternalURL { url: &self.host})?
-         .join(path).context(error::InternalURL { url: path })?;
+      let url = self.base_url.join(path).context(error::InternalURL { url: path })?;
 
-      let client = Client::new();
-      Ok(client.request(method, url)
+      Ok(self.client.request(method, url)
          .header("APCA-API-KEY-ID", self.api_key.clone())
          .header("APCA-API-SECRET-KEY", self.api_secret.clone()))
    }
-}
\ No newline at end of file
+
+   /// Internal helper to send a request to Alpaca, retrying it according to the retry policy.  Server errors
+   /// that persist through all of the retries are reported as Alpaca being down.
+   pub(crate) async fn send(&self, request: RequestBuilder) -> Result<Response> {
+      Ok(self.send_retried(request).await?.0)
+   }
+
+   /// Sends a request like `send`, also telling whether an earlier try may have reached Alpaca - it timed out
+   /// or had a server error - so that a rejection of the final try may be down to an earlier one working
+   pub(crate) async fn send_retried(&self, request: RequestBuilder) -> Result<(Response, bool)> {
+      let request = request.build().context(error::RequestFailed)?;
+      let max_retries = self.retry_policy.retries_for(&request);
+      let priority = Priority::of(request.method());
+
+      let mut retries = 0;
+      let mut may_have_reached = false;
+      loop {
+         let attempt = match request.try_clone() {
+            Some(attempt) if retries < max_retries => attempt,
+            _ => return Ok((Alpaca::sent(self.execute(request, priority).await)?, may_have_reached))
+         };
+
+         let delay = match self.execute(attempt, priority).await {
+            Ok(response) if retry::is_retryable(response.status()) => {
+               may_have_reached |= response.status().is_server_error();
+               self.retry_policy.delay(retries, Some(&response))
+            },
+            Err(e) if e.is_connect() || e.is_timeout() => {
+               may_have_reached |= !e.is_connect();
+               self.retry_policy.delay(retries, None)
+            },
+            result => return Ok((Alpaca::sent(result)?, may_have_reached))
+         };
+         delay_for(delay).await;
+         retries += 1;
+      }
+   }
+
+   /// Internal helper to send a request to Alpaca and check that it was accepted - see `error::failed` for
+   /// how failed calls are reported
+   pub(crate) async fn call(&self, request: RequestBuilder) -> Result<Response> {
+      let response = self.send(request).await?;
+      if response.status().is_success() { return Ok(response) }
+
+      Err(error::failed(response).await)?
+   }
+
+   /// Sends a single try of a request once the rate limiter allows it, and notes the rate limit status
+   async fn execute(&self, request: Request, priority: Priority) -> reqwest::Result<Response> {
+      if let Some(limiter) = &self.limiter { limiter.acquire(priority).await; }
+
+      let response = self.client.execute(request).await?;
+      if let Some(status) = RateLimitStatus::from_response(&response) {
+         if let Some(limiter) = &self.limiter { limiter.sync(&status); }
+         *self.rate_limit_status.lock().unwrap() = Some(status);
+      }
+      Ok(response)
+   }
+
+   /// Maps the final result of sending a request
+   fn sent(result: reqwest::Result<Response>) -> Result<Response> {
+      let response = result.context(error::RequestFailed)?;
+      ensure!(!response.status().is_server_error(), error::AlpacaDown { status: response.status().as_u16() });
+
+      Ok(response)
+   }
+}
@@ -0,0 +1,177 @@
+use reqwest::Method;
+use serde::{ Deserialize, Serialize };
+use snafu::ResultExt;
+
+use crate::{ error, Alpaca, Result };
+
+/// The class of an asset
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AssetClass {
+   /// Cryptocurrencies
+   Crypto,
+
+   /// US equities
+   UsEquity,
+
+   /// A class this library doesn't know about yet
+   #[serde(other)] Unknown
+}
+
+/// The exchange an asset is listed on
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+pub enum Exchange {
+   /// NYSE American (formerly the American Stock Exchange)
+   AMEX,
+
+   /// NYSE Arca
+   ARCA,
+
+   /// Cboe BZX (formerly BATS)
+   BATS,
+
+   /// Nasdaq
+   NASDAQ,
+
+   /// The New York Stock Exchange
+   NYSE,
+
+   /// NYSE Arca, as Alpaca names it for some assets
+   NYSEARCA,
+
+   /// Over the counter
+   OTC,
+
+   /// An exchange this library doesn't know about yet - such as a crypto venue
+   #[serde(other)] Unknown
+}
+
+/// Whether an asset is currently listed
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AssetStatus {
+   /// The asset is listed and can be traded, subject to the `is_tradable` flag
+   Active,
+
+   /// The asset has been delisted or is otherwise unavailable
+   Inactive
+}
+
+/// An asset that can be (or used to be) traded through Alpaca.
+///
+/// The flags on an asset should be checked before placing an order - for example a short sell can
+/// only be placed on an asset that is shortable.
+#[derive(Debug, Deserialize)]
+pub struct Asset {
+   /// Asset ID - a UUID
+   pub id: String,
+
+   /// The class of the asset
+   pub class: AssetClass,
+
+   /// The exchange the asset is listed on
+   pub exchange: Exchange,
+
+   /// Asset symbol
+   pub symbol: String,
+
+   /// The full name of the asset
+   pub name: String,
+
+   /// Whether the asset is active or not
+   pub status: AssetStatus,
+
+   /// If true, the asset is tradable on Alpaca
+   #[serde(rename = "tradable")] pub is_tradable: bool,
+
+   /// If true, the asset is marginable
+   #[serde(rename = "marginable")] pub is_marginable: bool,
+
+   /// If true, the asset is shortable
+   #[serde(rename = "shortable")] pub is_shortable: bool,
+
+   /// If true, the asset is easy to borrow for short sales
+   #[serde(rename = "easy_to_borrow")] pub is_easy_to_borrow: bool,
+
+   /// If true, the asset can be traded in fractional shares
+   #[serde(default, rename = "fractionable")] pub is_fractionable: bool,
+}
+impl Asset {
+   /// Gets a single asset by its symbol or asset ID.
+   ///
+   /// # Example
+   ///
+   /// To check whether AAPL can be shorted:
+   ///
+   /// ``` no run
+   /// let alpaca = Alpaca::live("KEY_ID", "SECRET").await.unwrap();
+   ///
+   /// let asset = Asset::get(&alpaca, "AAPL").await.unwrap();
+   /// println!("AAPL is shortable: {}", asset.is_shortable);
+   /// ```
+   pub async fn get(alpaca: &Alpaca, symbol_or_id: &str) -> Result<Asset> {
+      let response = alpaca.send(alpaca.request(Method::GET, format!("v2/assets/{}", symbol_or_id).as_str())?).await?;
+
+      if response.status().is_success() { return Ok(response.json::<Asset>().await.context(error::BadData)?) }
+      match response.status().as_u16() {
+         404 => error::AssetNotFound { symbol: symbol_or_id }.fail()?,
+         _ => Err(error::failed(response).await)?
+      }
+   }
+
+   /// Gets a list of assets, limited to the ones that match the filter.
+   ///
+   /// # Example
+   ///
+   /// To get all of the active assets on the NYSE:
+   ///
+   /// ``` no run
+   /// let alpaca = Alpaca::live("KEY_ID", "SECRET").await.unwrap();
+   ///
+   /// let filter = AssetFilter::default()
+   ///    .status(AssetStatus::Active)
+   ///    .exchange(Exchange::NYSE);
+   /// let assets = Asset::list(&alpaca, &filter).await.unwrap();
+   /// ```
+   pub async fn list(alpaca: &Alpaca, filter: &AssetFilter) -> Result<Vec<Asset>> {
+      let request = alpaca.request(Method::GET, "v2/assets")?
+         .query(filter);
+      let response = alpaca.call(request).await?;
+
+      Ok(response.json::<Vec<Asset>>().await.context(error::BadData)?)
+   }
+}
+
+/// Limits the assets returned by Asset.list
+///
+/// By default no filtering is done and all assets are returned.
+#[derive(Debug, Default, Serialize)]
+pub struct AssetFilter {
+   /// Only return assets with this status
+   #[serde(skip_serializing_if = "Option::is_none")] status: Option<AssetStatus>,
+
+   /// Only return assets of this class
+   #[serde(rename = "asset_class", skip_serializing_if = "Option::is_none")] class: Option<AssetClass>,
+
+   /// Only return assets listed on this exchange
+   #[serde(skip_serializing_if = "Option::is_none")] exchange: Option<Exchange>,
+}
+impl AssetFilter {
+   /// Only return assets with the given status
+   pub fn status(mut self, status: AssetStatus) -> AssetFilter {
+      self.status = Some(status);
+      self
+   }
+
+   /// Only return assets of the given class
+   pub fn class(mut self, class: AssetClass) -> AssetFilter {
+      self.class = Some(class);
+      self
+   }
+
+   /// Only return assets listed on the given exchange
+   pub fn exchange(mut self, exchange: Exchange) -> AssetFilter {
+      self.exchange = Some(exchange);
+      self
+   }
+}
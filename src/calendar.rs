@@ -0,0 +1,45 @@
+use chrono::{ NaiveDate, NaiveTime };
+use reqwest::Method;
+use serde::Deserialize;
+use snafu::ResultExt;
+
+use crate::{ error, util, Alpaca, Result };
+
+/// A single trading day in the market calendar.
+///
+/// Open and close times are in the market's local time (US Eastern) and take early closes into
+/// account.
+#[derive(Debug, Deserialize)]
+pub struct Calendar {
+   /// The date of the trading day
+   pub date: NaiveDate,
+
+   /// The time the market opens on this day
+   #[serde(deserialize_with = "util::to_naive_time")] pub open: NaiveTime,
+
+   /// The time the market closes on this day
+   #[serde(deserialize_with = "util::to_naive_time")] pub close: NaiveTime,
+}
+impl Calendar {
+   /// Gets the trading days between the start and end dates (inclusive).  Days the market is closed
+   /// are not returned.
+   ///
+   /// # Example
+   ///
+   /// To get the trading days in January 2020:
+   ///
+   /// ``` no run
+   /// let alpaca = Alpaca::live("KEY_ID", "SECRET").await.unwrap();
+   ///
+   /// let start = NaiveDate::from_ymd_opt(2020, 1, 1).unwrap();
+   /// let end = NaiveDate::from_ymd_opt(2020, 1, 31).unwrap();
+   /// let days = Calendar::get(&alpaca, start, end).await.unwrap();
+   /// ```
+   pub async fn get(alpaca: &Alpaca, start: NaiveDate, end: NaiveDate) -> Result<Vec<Calendar>> {
+      let request = alpaca.request(Method::GET, "v2/calendar")?
+         .query(&[("start", start.to_string()), ("end", end.to_string())]);
+      let response = alpaca.call(request).await?;
+
+      Ok(response.json::<Vec<Calendar>>().await.context(error::BadData)?)
+   }
+}
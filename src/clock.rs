@@ -0,0 +1,43 @@
+use chrono::{ DateTime, Utc };
+use reqwest::Method;
+use serde::Deserialize;
+use snafu::ResultExt;
+
+use crate::{ error, Alpaca, Result };
+
+/// The market clock.
+///
+/// Tells whether the market is open right now and when it will next open and close.
+#[derive(Debug, Deserialize)]
+pub struct Clock {
+   /// The current time according to Alpaca
+   pub timestamp: DateTime<Utc>,
+
+   /// If true, the market is open right now
+   pub is_open: bool,
+
+   /// The next time the market will open
+   pub next_open: DateTime<Utc>,
+
+   /// The next time the market will close
+   pub next_close: DateTime<Utc>,
+}
+impl Clock {
+   /// Gets the current market clock
+   ///
+   /// # Example
+   ///
+   /// To find out if the market is open:
+   ///
+   /// ``` no run
+   /// let alpaca = Alpaca::live("KEY_ID", "SECRET").await.unwrap();
+   ///
+   /// let clock = Clock::get(&alpaca).await.unwrap();
+   /// println!("The market is open: {}", clock.is_open);
+   /// ```
+   pub async fn get(alpaca: &Alpaca) -> Result<Clock> {
+      let response = alpaca.call(alpaca.request(Method::GET, "v2/clock")?).await?;
+
+      Ok(response.json::<Clock>().await.context(error::BadData)?)
+   }
+}
@@ -1,16 +1,60 @@
+use reqwest::Response;
+use serde::Deserialize;
 use snafu::Snafu;
 
+use crate::Order;
+
+/// The body Alpaca sends back when a call fails
+#[derive(Debug, Default, Deserialize)]
+pub(crate) struct ApiError {
+   #[serde(default)] pub code: Option<u64>,
+   #[serde(default)] pub message: String
+}
+impl ApiError {
+   /// Reads the error from the body of a failed call - leaving it empty if there isn't one
+   pub async fn from_response(response: Response) -> ApiError {
+      response.json::<ApiError>().await.unwrap_or_default()
+   }
+}
+
+/// The code Alpaca gives an order rejected because its client order ID was already used
+const DUPLICATE_CLIENT_ORDER_ID_CODE: u64 = 40010001;
+
+/// True if an order was rejected because its client order ID was already used - going by Alpaca's code,
+/// or by its message if there is no code
+pub(crate) fn is_duplicate_client_order_id(code: Option<u64>, message: &str) -> bool {
+   match code {
+      Some(code) => code == DUPLICATE_CLIENT_ORDER_ID_CODE,
+      None => message.contains("client_order_id must be unique")
+   }
+}
+
 #[derive(Debug, Snafu)]
 #[snafu(visibility = "pub(crate)")]
 pub enum InnerError {
-   #[snafu(display("Alpaca is unavailable right now"))]
-   AlpacaDown,
+   #[snafu(display("Alpaca is unavailable right now - it returned a {}", status))]
+   AlpacaDown { status: u16 },
+
+   #[snafu(display("The asset '{}' was not found", symbol))]
+   AssetNotFound { symbol: String },
 
    #[snafu(display("Alpaca returned invalid data - {}", source.to_string()))]
    BadData { source: reqwest::Error },
 
-   #[snafu(display("Alpaca call failed. '{}' returned a {} result.", url, status))]
-   CallFailed { url: String, status: u16 },
+   #[snafu(display("Line {} of the recording '{}' could not be read - {}", line, path, source.to_string()))]
+   BadRecording { path: String, line: usize, source: serde_json::Error },
+
+   #[snafu(display("The HTTP client could not be configured - {}", source.to_string()))]
+   ClientConfig { source: reqwest::Error },
+
+   #[snafu(display("An order with the client order ID '{}' already exists", client_order_id))]
+   DuplicateClientOrderId { client_order_id: String, order: Box<Order> },
+
+   #[snafu(display("Alpaca refused the call.  {}", message))]
+   Forbidden { code: Option<u64>, message: String },
+
+   #[snafu(display("{}", message))]
+   Fanout { kind: ErrorKind, message: String },
 
    #[snafu(display("An internal error occurred"))]
    InternalJSON { source: serde_json::Error },
@@ -18,11 +62,26 @@ pub enum InnerError {
    #[snafu(display("An internal error occurred - please report that '{}' cannot be parsed because {}", url, source.to_string()))]
    InternalURL { url: String, source: url::ParseError },
 
+   #[snafu(display("The call was rejected as invalid.  {}", message))]
+   Invalid { code: Option<u64>, message: String },
+
+   #[snafu(display("The endpoint URL '{}' is not valid - {}", url, source.to_string()))]
+   InvalidEndpoint { url: String, source: url::ParseError },
+
    #[snafu(display("The key ID or secret key were not accepted"))]
-   InvalidCredentials,
+   InvalidCredentials { status: Option<u16> },
 
-   #[snafu(display("The order cannot be submitted due to lack of buying power"))]
-   OrderForbidden,
+   #[snafu(display("'{}' was not found", url))]
+   NotFound { url: String },
+
+   #[snafu(display("The subscriber fell too far behind the stream and was dropped"))]
+   Lagged,
+
+   #[snafu(display("The environment variable '{}' is not set", name))]
+   MissingEnvironment { name: String, source: std::env::VarError },
+
+   #[snafu(display("Alpaca refused the order.  {}", message))]
+   OrderForbidden { code: Option<u64>, message: String },
 
    #[snafu(display("The order is invalid.  {}", reason))]
    OrderInvalid { reason: String },
@@ -33,12 +92,196 @@ pub enum InnerError {
    #[snafu(display("The order '{}' was not found", order_id))]
    OrderNotFound { order_id: String },
 
+   #[snafu(display("The position in '{}' cannot be closed", symbol))]
+   PositionNotClosable { symbol: String },
+
+   #[snafu(display("There is no open position in '{}'", symbol))]
+   PositionNotFound { symbol: String },
+
+   #[snafu(display("Too many calls have been made - Alpaca is rate limiting"))]
+   RateLimited,
+
+   #[snafu(display("The recording '{}' could not be used - {}", path, source.to_string()))]
+   Recording { path: String, source: std::io::Error },
+
+   #[snafu(display("Alpaca rejected the call with a {} result.  {}", status, message))]
+   Rejected { status: u16, code: Option<u64>, message: String },
+
    #[snafu(display("Alpaca call failed for unknown reason."))]
    RequestFailed { source: reqwest::Error },
 
    #[snafu(display("Alpaca websocket connection failed for unknown reason."))]
    StreamingFailed { source: tungstenite::Error },
 
+   #[snafu(display("The Alpaca websocket stream could not be set up - {}", reason))]
+   StreamSetup { reason: String },
+
+   #[snafu(display("The Alpaca websocket stream has stopped"))]
+   StreamStopped,
+
    #[snafu(display("An unexpected error occurred"))]
    Unknown
-}
\ No newline at end of file
+}
+
+/// Works out the error for a call that Alpaca did not accept:
+///  * 401 - the credentials were not accepted
+///  * 403 - the call was refused, or the credentials were not accepted if Alpaca gives no reason
+///  * 404 - what the call was for does not exist
+///  * 422 - the call was invalid, with Alpaca's reason
+///  * 429 - too many calls are being made
+///  * 5xx - Alpaca is down
+///
+/// Endpoints that know more about what a status means (such as an order not being found) check for it
+/// first and use this for everything else.
+pub(crate) async fn failed(response: Response) -> InnerError {
+   let status = response.status().as_u16();
+   let url = response.url().to_string();
+   let body = ApiError::from_response(response).await;
+
+   match status {
+      401 => InnerError::InvalidCredentials { status: Some(status) },
+      403 if body.code.is_none() => InnerError::InvalidCredentials { status: Some(status) },
+      403 => InnerError::Forbidden { code: body.code, message: body.message },
+      404 => InnerError::NotFound { url },
+      422 => InnerError::Invalid { code: body.code, message: body.message },
+      429 => InnerError::RateLimited,
+      500..=599 => InnerError::AlpacaDown { status },
+      _ => InnerError::Rejected { status, code: body.code, message: body.message }
+   }
+}
+
+/// Works out the error for one entry of a multi-status (207) result, such as canceling all orders, in the
+/// same way as `failed`.  Endpoints check first for the statuses they know more about.
+pub(crate) fn failed_entry(status: u16, body: ApiError) -> InnerError {
+   match status {
+      403 => InnerError::Forbidden { code: body.code, message: body.message },
+      429 => InnerError::RateLimited,
+      500..=599 => InnerError::AlpacaDown { status },
+      _ => InnerError::Rejected { status, code: body.code, message: body.message }
+   }
+}
+
+impl InnerError {
+   /// The kind of error, for callers to act on
+   pub fn kind(&self) -> ErrorKind {
+      match self {
+         InnerError::AlpacaDown { .. } => ErrorKind::AlpacaDown,
+         InnerError::AssetNotFound { .. } | InnerError::NotFound { .. } | InnerError::OrderNotFound { .. } | InnerError::PositionNotFound { .. } => ErrorKind::NotFound,
+         InnerError::BadData { .. } | InnerError::BadRecording { .. } => ErrorKind::BadData,
+         InnerError::Rejected { status, .. } => ErrorKind::from_status(*status),
+         InnerError::ClientConfig { .. } | InnerError::InvalidEndpoint { .. } | InnerError::MissingEnvironment { .. } | InnerError::Recording { .. } => ErrorKind::Configuration,
+         InnerError::DuplicateClientOrderId { .. } => ErrorKind::DuplicateClientOrderId,
+         InnerError::Fanout { kind, .. } => *kind,
+         InnerError::InternalJSON { .. } | InnerError::InternalURL { .. } => ErrorKind::Internal,
+         InnerError::InvalidCredentials { .. } => ErrorKind::InvalidCredentials,
+         InnerError::Lagged => ErrorKind::Lagged,
+         InnerError::Forbidden { .. } | InnerError::OrderForbidden { .. } => ErrorKind::Forbidden,
+         InnerError::Invalid { .. } | InnerError::OrderInvalid { .. } => ErrorKind::Invalid,
+         InnerError::RateLimited => ErrorKind::RateLimited,
+         InnerError::OrderNotCancelable { .. } | InnerError::PositionNotClosable { .. } => ErrorKind::NotCancelable,
+         InnerError::RequestFailed { source } if source.is_connect() || source.is_timeout() || source.is_request() => ErrorKind::Network,
+         InnerError::RequestFailed { source } if source.is_builder() => ErrorKind::Invalid,
+         InnerError::RequestFailed { source } if source.is_body() || source.is_decode() => ErrorKind::BadData,
+         InnerError::RequestFailed { .. } => ErrorKind::Unknown,
+         InnerError::StreamingFailed { .. } | InnerError::StreamSetup { .. } | InnerError::StreamStopped => ErrorKind::Streaming,
+         InnerError::Unknown => ErrorKind::Unknown
+      }
+   }
+
+   /// The HTTP status Alpaca returned, if the error came from a response
+   pub fn http_status(&self) -> Option<u16> {
+      match self {
+         InnerError::AlpacaDown { status } | InnerError::Rejected { status, .. } => Some(*status),
+         InnerError::InvalidCredentials { status } => *status,
+         InnerError::RequestFailed { source } | InnerError::BadData { source } => source.status().map(|status| status.as_u16()),
+         InnerError::Forbidden { .. } | InnerError::OrderForbidden { .. } => Some(403),
+         InnerError::AssetNotFound { .. } | InnerError::NotFound { .. } | InnerError::OrderNotFound { .. } | InnerError::PositionNotFound { .. } => Some(404),
+         InnerError::DuplicateClientOrderId { .. } | InnerError::Invalid { .. } | InnerError::OrderNotCancelable { .. } | InnerError::PositionNotClosable { .. } => Some(422),
+         InnerError::RateLimited => Some(429),
+         _ => None
+      }
+   }
+
+   /// The error code in the body Alpaca returned, if it sent one
+   pub fn api_code(&self) -> Option<u64> {
+      match self {
+         InnerError::Forbidden { code, .. } | InnerError::Invalid { code, .. } | InnerError::OrderForbidden { code, .. } |
+         InnerError::Rejected { code, .. } => *code,
+         _ => None
+      }
+   }
+}
+
+/// The kinds of error that can be hit when calling Alpaca.
+///
+/// More kinds may be added over time, so matches need a catch-all arm.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ErrorKind {
+   /// Alpaca has a server error (5xx)
+   AlpacaDown,
+
+   /// Alpaca returned data that could not be read - or a recording of it is not valid
+   BadData,
+
+   /// The client settings, endpoint, environment or recording file are not valid
+   Configuration,
+
+   /// An order was rejected because its client order ID was already used
+   DuplicateClientOrderId,
+
+   /// Alpaca refused the call - such as an order without enough buying power
+   Forbidden,
+
+   /// An internal error in this library
+   Internal,
+
+   /// The call was invalid - either checked before sending or rejected by Alpaca (422)
+   Invalid,
+
+   /// The key ID or secret key were not accepted
+   InvalidCredentials,
+
+   /// A stream subscriber fell too far behind and was dropped
+   Lagged,
+
+   /// The call could not reach Alpaca, or timed out
+   Network,
+
+   /// The order cannot be canceled or the position cannot be closed
+   NotCancelable,
+
+   /// The order, position or asset does not exist (404)
+   NotFound,
+
+   /// Too many calls have been made (429)
+   RateLimited,
+
+   /// Alpaca rejected the call for some other reason
+   Rejected,
+
+   /// The websocket stream failed
+   Streaming,
+
+   /// An unexpected error occurred
+   Unknown
+}
+impl ErrorKind {
+   /// Works out the kind of error from the HTTP status of a failed call
+   pub(crate) fn from_status(status: u16) -> ErrorKind {
+      match status {
+         401 => ErrorKind::InvalidCredentials,
+         403 => ErrorKind::Forbidden,
+         404 => ErrorKind::NotFound,
+         422 => ErrorKind::Invalid,
+         429 => ErrorKind::RateLimited,
+         500..=599 => ErrorKind::AlpacaDown,
+         _ => ErrorKind::Rejected
+      }
+   }
+
+   /// True if trying the same call again later might work
+   pub fn is_retryable(self) -> bool {
+      matches!(self, ErrorKind::AlpacaDown | ErrorKind::Network | ErrorKind::RateLimited)
+   }
+}
@@ -0,0 +1,294 @@
+use futures::{ Stream, StreamExt };
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::sync::{ Arc, Mutex, Weak };
+use std::task::{ Context, Poll, Waker };
+
+use crate::{ error, OrderEventType, Result, StreamHandle, StreamKind, StreamMessage, Subscription };
+
+/// What a subscriber wants from the stream.
+///
+/// The filter picks which account and order events a subscriber gets - by default it gets all of them.
+/// Everything else on the stream, such as `Reconnected` markers and errors, goes to every subscriber.
+///
+/// # Example
+///
+/// To get the fills for our momentum strategy's AAPL orders:
+///
+/// ``` no run
+/// let filter = EventFilter::new()
+///    .order_event(OrderEventType::Fill)
+///    .symbol("AAPL")
+///    .client_order_id_prefix("momentum-");
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct EventFilter {
+   streams: Vec<StreamKind>,
+   order_events: Vec<OrderEventType>,
+   symbols: Vec<String>,
+   client_order_id_prefix: Option<String>
+}
+impl EventFilter {
+   /// Creates a filter that lets everything through
+   pub fn new() -> EventFilter { EventFilter::default() }
+
+   /// Only lets through events from the stream - can be used more than once to allow several streams
+   pub fn stream(mut self, stream: StreamKind) -> EventFilter {
+      self.streams.push(stream);
+      self
+   }
+
+   /// Only lets through order events of the type - can be used more than once to allow several types
+   pub fn order_event(mut self, event_type: OrderEventType) -> EventFilter {
+      self.order_events.push(event_type);
+      self
+   }
+
+   /// Only lets through order events for the symbol - can be used more than once to allow several symbols
+   pub fn symbol(mut self, symbol: &str) -> EventFilter {
+      self.symbols.push(symbol.to_string());
+      self
+   }
+
+   /// Only lets through order events where the client order ID starts with the prefix
+   pub fn client_order_id_prefix(mut self, prefix: &str) -> EventFilter {
+      self.client_order_id_prefix = Some(prefix.to_string());
+      self
+   }
+
+   /// True if the message should go to the subscriber.  Account events don't have a symbol or client order
+   /// ID, so they are filtered out by those.
+   fn matches(&self, msg: &StreamMessage) -> bool {
+      let allows = |stream| self.streams.is_empty() || self.streams.contains(&stream);
+      match msg {
+         StreamMessage::Account(_) => allows(StreamKind::Account) && self.symbols.is_empty() && self.client_order_id_prefix.is_none(),
+         StreamMessage::Order(event) => {
+            let order = event.order();
+            allows(StreamKind::Order)
+               && (self.order_events.is_empty() || self.order_events.contains(&event.event_type()))
+               && (self.symbols.is_empty() || self.symbols.contains(&order.symbol))
+               && self.client_order_id_prefix.as_ref().is_none_or(|prefix| order.client_order_id.starts_with(prefix))
+         },
+         _ => true
+      }
+   }
+}
+
+/// What happens when a subscriber falls behind and its queue is full
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LagPolicy {
+   /// Drop the oldest queued message to make room for the new one
+   DropOldest,
+
+   /// Pass on what is queued, then a `Lagged` error, and drop the subscriber
+   Error
+}
+
+/// How a subscriber is keeping up with the stream
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct LagMetrics {
+   /// The number of messages the subscriber has taken from its queue
+   pub delivered: u64,
+
+   /// The number of messages lost because the queue was full
+   pub dropped: u64,
+
+   /// The number of messages waiting in the queue
+   pub queued: usize,
+
+   /// The most messages that have been waiting in the queue at once
+   pub max_queued: usize
+}
+
+/// The queue of messages waiting for a subscriber
+struct Queue {
+   filter: EventFilter,
+   capacity: usize,
+   lag_policy: LagPolicy,
+   items: VecDeque<Result<Arc<StreamMessage>>>,
+   closed: bool,
+   waker: Option<Waker>,
+   metrics: LagMetrics
+}
+impl Queue {
+   /// Adds a message to the queue if the subscriber wants it
+   fn offer(&mut self, item: &Result<Arc<StreamMessage>>) {
+      if self.closed { return; }
+      let item = match item {
+         Ok(msg) if self.filter.matches(msg) => Ok(msg.clone()),
+         Ok(_) => return,
+         Err(e) => Err(error::InnerError::Fanout { kind: e.kind(), message: e.to_string() }.into())
+      };
+
+      if self.items.len() >= self.capacity {
+         self.metrics.dropped += 1;
+         match self.lag_policy {
+            LagPolicy::DropOldest => { self.items.pop_front(); },
+            LagPolicy::Error => {
+               self.items.push_back(Err(error::InnerError::Lagged.into()));
+               self.close();
+               return;
+            }
+         }
+      }
+      self.items.push_back(item);
+      self.metrics.queued = self.items.len();
+      self.metrics.max_queued = self.metrics.max_queued.max(self.items.len());
+      self.wake();
+   }
+
+   /// Ends the subscriber's stream once it has taken what is queued
+   fn close(&mut self) {
+      self.closed = true;
+      self.metrics.queued = self.items.len();
+      self.wake();
+   }
+
+   fn wake(&mut self) {
+      if let Some(waker) = self.waker.take() { waker.wake(); }
+   }
+}
+
+/// The subscribers to a hub, and where the stream is at
+struct Fanout {
+   queues: Vec<Weak<Mutex<Queue>>>,
+   ended: bool,
+   hub_dropped: bool
+}
+impl Fanout {
+   /// True if no subscriber is left to take messages, other than the one given (which is going away)
+   fn is_unused(&self, leaving: Option<&Arc<Mutex<Queue>>>) -> bool {
+      self.queues.iter()
+         .filter_map(|queue| queue.upgrade())
+         .all(|queue| leaving.is_some_and(|leaving| Arc::ptr_eq(&queue, leaving)))
+   }
+}
+
+/// Shares one stream of events with any number of subscribers.
+///
+/// Alpaca allows one stream connection per key, so the hub takes the subscription from a single streamer
+/// and passes each message on to every subscriber that wants it.  Each subscriber has its own filter and
+/// its own queue, so a slow subscriber doesn't hold up the others - what happens when it falls behind is
+/// set by its lag policy.
+///
+/// Messages are held until the first subscriber joins, so it gets everything from the start of the
+/// stream.  Later subscribers only get the messages that arrive after they subscribe.  The streams of
+/// subscribers end when the streamer stops, and the streamer is stopped once the hub and all of its
+/// subscribers have been dropped.
+///
+/// # Example
+///
+/// To share the stream between order tracking and a fill logger:
+///
+/// ``` no run
+/// let hub = StreamHub::new(Streamer::new(&alpaca).start().await.unwrap());
+///
+/// let orders = hub.subscribe(EventFilter::new().stream(StreamKind::Order), 1000, LagPolicy::Error);
+/// let fills = hub.subscribe(EventFilter::new().order_event(OrderEventType::Fill), 100, LagPolicy::DropOldest);
+/// ```
+pub struct StreamHub {
+   handle: StreamHandle,
+   fanout: Arc<Mutex<Fanout>>,
+   subscription: Mutex<Option<Subscription>>
+}
+impl StreamHub {
+   /// Creates a hub that shares the subscription's messages
+   pub fn new(subscription: Subscription) -> StreamHub {
+      let fanout = Fanout { queues: Vec::new(), ended: false, hub_dropped: false };
+
+      StreamHub { handle: subscription.handle(), fanout: Arc::new(Mutex::new(fanout)), subscription: Mutex::new(Some(subscription)) }
+   }
+
+   /// Adds a subscriber that gets the messages let through by the filter.  Up to `capacity` messages are
+   /// queued for it before the lag policy kicks in.  If the stream has already ended, so has the
+   /// subscriber's.
+   ///
+   /// The first subscriber starts passing on the messages in a task on the tokio runtime, so it must be
+   /// added from within the runtime - otherwise this panics.
+   pub fn subscribe(&self, filter: EventFilter, capacity: usize, lag_policy: LagPolicy) -> HubSubscriber {
+      let queue = {
+         let mut fanout = self.fanout.lock().unwrap();
+         let queue = Arc::new(Mutex::new(Queue {
+            filter,
+            capacity: capacity.max(1),
+            lag_policy,
+            items: VecDeque::new(),
+            closed: fanout.ended,
+            waker: None,
+            metrics: LagMetrics::default()
+         }));
+         fanout.queues.push(Arc::downgrade(&queue));
+         queue
+      };
+
+      // the first subscriber starts the messages flowing
+      if let Some(subscription) = self.subscription.lock().unwrap().take() {
+         tokio::spawn(fan_out(subscription, self.fanout.clone()));
+      }
+
+      HubSubscriber { queue, fanout: self.fanout.clone(), handle: self.handle.clone() }
+   }
+
+   /// Gets a handle on the streamer behind the hub - to change its streams or stop it
+   pub fn handle(&self) -> StreamHandle { self.handle.clone() }
+}
+impl Drop for StreamHub {
+   fn drop(&mut self) {
+      let mut fanout = self.fanout.lock().unwrap();
+      fanout.hub_dropped = true;
+      if fanout.is_unused(None) { self.handle.request_stop(); }
+   }
+}
+
+/// Passes each message from the subscription on to the subscribers, closing their queues when it ends
+async fn fan_out(mut subscription: Subscription, fanout: Arc<Mutex<Fanout>>) {
+   while let Some(item) = subscription.next().await {
+      let item = item.map(Arc::new);
+      fanout.lock().unwrap().queues.retain(|queue| match queue.upgrade() {
+         Some(queue) => { queue.lock().unwrap().offer(&item); true },
+         None => false
+      });
+   }
+
+   let mut fanout = fanout.lock().unwrap();
+   fanout.ended = true;
+   for queue in fanout.queues.drain(..).filter_map(|queue| queue.upgrade()) {
+      queue.lock().unwrap().close();
+   }
+}
+
+/// The stream of messages for one subscriber to a hub
+pub struct HubSubscriber {
+   queue: Arc<Mutex<Queue>>,
+   fanout: Arc<Mutex<Fanout>>,
+   handle: StreamHandle
+}
+impl HubSubscriber {
+   /// How the subscriber is keeping up with the stream
+   pub fn metrics(&self) -> LagMetrics { self.queue.lock().unwrap().metrics }
+}
+impl Drop for HubSubscriber {
+   fn drop(&mut self) {
+      let fanout = self.fanout.lock().unwrap();
+      if fanout.hub_dropped && fanout.is_unused(Some(&self.queue)) { self.handle.request_stop(); }
+   }
+}
+impl Stream for HubSubscriber {
+   type Item = Result<Arc<StreamMessage>>;
+
+   fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+      let mut queue = self.queue.lock().unwrap();
+      match queue.items.pop_front() {
+         Some(item) => {
+            queue.metrics.delivered += 1;
+            queue.metrics.queued = queue.items.len();
+            Poll::Ready(Some(item))
+         },
+         None if queue.closed => Poll::Ready(None),
+         None => {
+            queue.waker = Some(cx.waker().clone());
+            Poll::Pending
+         }
+      }
+   }
+}
@@ -8,7 +8,10 @@
 //! Currently `alpaca_finance` provides:
 //! * Access and authentication against the paper trading and live trading APIs
 //! * Account API to get important information about your account
-//! * Orders API to place, replace, cancel and get open orders.
+//! * Assets API to look up what can be traded, shorted or bought in fractions
+//! * Clock and calendar APIs to find out when the market is open
+//! * Orders API to place, replace, cancel, get open orders and page through the order history.
+//! * Positions API to get and close open positions.
 //! * Realtime streaming updates to orders and account changes
 //!
 //! ## Quick Examples
@@ -37,7 +40,7 @@
 //! async fn main() {
 //!    // Get a connection to the live API
 //!    let alpaca = Alpaca::paper("My KEY ID", "My Secret Key").await.unwrap();
-//!    let order = Order::buy("AAPL", 100, OrderType::Limit, TimeInForce::DAY)
+//!    let order = Order::buy("AAPL", 100.0, OrderType::Limit, TimeInForce::DAY)
 //!       .limit_price(100.0)
 //!       .place(sandbox).await.unwrap();
 //! }
@@ -55,12 +58,13 @@
 //!    let alpaca = Alpaca::paper("My KEY ID", "My Secret Key").await.unwrap();
 //!
 //!    let streamer = Streamer:new(&alpaca);
-//!    streamer.start().await
+//!    streamer.start().await.unwrap()
 //!       .for_each(|msg| {
 //!          match msg {
-//!             StreamMessage::Account(_) => println!("Got an account update!"),
-//!             StreamMessage::Order(_) => println!("Got an order update!"),
-//!             _ => println!("Got an unexpected msg")
+//!             Ok(StreamMessage::Account(_)) => println!("Got an account update!"),
+//!             Ok(StreamMessage::Order(_)) => println!("Got an order update!"),
+//!             Ok(_) => println!("Got an unexpected msg"),
+//!             Err(e) => println!("Something went wrong - {}", e)
 //!          }
 //!          future::ready(())
 //!       })
@@ -73,22 +77,83 @@ mod account;
 pub use account::{ Account, AccountStatus };
 
 mod alpaca;
-pub use alpaca::Alpaca;
+pub use alpaca::{ Alpaca, AlpacaBuilder, Endpoint };
+
+mod asset;
+pub use asset::{ Asset, AssetClass, AssetFilter, AssetStatus, Exchange };
+
+mod calendar;
+pub use calendar::Calendar;
+
+mod clock;
+pub use clock::Clock;
 
 mod error;
+pub use error::ErrorKind;
 use snafu::Snafu;
 
 /// An opaque error hit when calling Alpaca
 #[derive(Debug, Snafu)]
 pub struct Error(error::InnerError);
 
+impl Error {
+   /// The kind of error - for deciding what to do about it
+   ///
+   /// # Example
+   ///
+   /// To wait and try again if the call might work later:
+   ///
+   /// ``` no run
+   /// match Account::get(&alpaca).await {
+   ///    Err(e) if e.kind() == ErrorKind::RateLimited => delay_for(Duration::from_secs(1)).await,
+   ///    ...
+   /// }
+   /// ```
+   pub fn kind(&self) -> ErrorKind { self.0.kind() }
+
+   /// True if trying the same call again later might work - such as when Alpaca is down or rate limiting
+   pub fn is_retryable(&self) -> bool { self.kind().is_retryable() }
+
+   /// The HTTP status Alpaca returned, if the error came from a response
+   pub fn http_status(&self) -> Option<u16> { self.0.http_status() }
+
+   /// The error code in the body Alpaca returned, if it sent one
+   pub fn api_code(&self) -> Option<u64> { self.0.api_code() }
+
+   /// The order that already exists when a new order is rejected for reusing its client order ID
+   pub fn existing_order(&self) -> Option<&Order> {
+      match &self.0 {
+         error::InnerError::DuplicateClientOrderId { order, .. } => Some(order),
+         _ => None
+      }
+   }
+}
+
 /// The result of an operation
 pub type Result<T> = std::result::Result<T, Error>;
 
+mod hub;
+pub use hub::{ EventFilter, HubSubscriber, LagMetrics, LagPolicy, StreamHub };
+
 mod order;
-pub use order::{ Order, OrderBuilder, OrderStatus, OrderType, OrderUpdater, TimeInForce };
+pub use order::{
+   ClientOrderIdGenerator, Order, OrderBuilder, OrderClass, OrderQuery, OrderQueryStatus, OrderSide, OrderStatus, OrderType,
+   OrderUpdater, PrefixedIdGenerator, SortDirection, StopLoss, TakeProfit, TimeInForce
+};
+
+mod position;
+pub use position::{ Position, PositionSide };
+
+mod rate_limit;
+pub use rate_limit::{ RateLimiter, RateLimitStatus };
+
+mod recording;
+pub use recording::{ Recorder, Replayer, ReplaySpeed };
+
+mod retry;
+pub use retry::RetryPolicy;
 
 mod streaming;
-pub use streaming::{ OrderEvent, Streamer, StreamMessage };
+pub use streaming::{ OrderEvent, OrderEventType, Streamer, StreamHandle, StreamKind, StreamMessage, StreamState, Subscription };
 
 mod util;
\ No newline at end of file
@@ -1,12 +1,34 @@
-use reqwest::Method;
+use chrono::{ DateTime, Duration, Utc };
+use futures::{ stream, Stream, TryStreamExt };
+use reqwest::{ Method, Response };
 use serde::{ Deserialize, Serialize };
 use snafu::{ ensure, ResultExt };
+use std::collections::{ HashMap, HashSet };
 use std::fmt;
+use std::sync::atomic::{ AtomicU64, Ordering };
 
 use crate::{ error, util, Alpaca, Result };
 
+/// The class of the order - whether it is a single order or has legs that are triggered by it
+#[derive(Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OrderClass {
+   /// An entry order with both a take profit and a stop loss exit order attached
+   Bracket,
+
+   /// One cancels other - a take profit and a stop loss exit order where filling one cancels the other
+   Oco,
+
+   /// One triggers other - an entry order with either a take profit or a stop loss exit order attached
+   Oto,
+
+   /// A single order with no legs
+   #[default]
+   #[serde(alias = "")] Simple
+}
+
 /// The side of the order - buy or sell
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
 #[serde(rename_all = "snake_case")]
 pub enum OrderSide {
    /// An order to 'buy'
@@ -81,7 +103,10 @@ pub enum OrderType {
    Market,
 
    Stop,
-   StopLimit
+   StopLimit,
+
+   /// A stop order whose stop price follows the market by a fixed price or percentage.
+   TrailingStop
 }
 
 /// The instruction used when placing a trade to indicate how long the order will remain active before it
@@ -128,23 +153,38 @@ pub struct Order {
    /// Client unique order id
    pub client_order_id: String,
 
+   /// Timestamp the order was created at
+   #[serde(rename = "created_at")] pub created: DateTime<Utc>,
+
    /// If true, eligible for execution outside regular trading hours.
    #[serde(rename = "extended_hours")] pub is_extended_hours: bool,
 
    /// Filled quantity
-   #[serde(deserialize_with = "util::to_u32")] pub filled_qty: u32,
+   #[serde(deserialize_with = "util::to_f64")] pub filled_qty: f64,
+
+   /// The highest (lowest for sell orders) price seen since a trailing stop order was placed
+   #[serde(default, deserialize_with = "util::to_optional_f64")] pub hwm: Option<f64>,
 
    /// Filled average price
    #[serde(deserialize_with = "util::to_optional_f64")] pub filled_avg_price: Option<f64>,
 
+   /// The legs of a bracket, OCO or OTO order - only set when the order is fetched with nested legs
+   #[serde(default)] pub legs: Option<Vec<Order>>,
+
    /// Limit price
    #[serde(deserialize_with = "util::to_optional_f64")] pub limit_price: Option<f64>,
 
+   /// Class of the order - simple, bracket, OCO or OTO
+   #[serde(default)] pub order_class: OrderClass,
+
    /// Type of order
    #[serde(rename = "type")] pub order_type: OrderType,
 
-   /// Ordered quantity
-   #[serde(deserialize_with = "util::to_u32")] pub qty: u32,
+   /// The dollar amount to trade - only set for notional orders
+   #[serde(default, deserialize_with = "util::to_optional_f64")] pub notional: Option<f64>,
+
+   /// Ordered quantity - not set for notional orders
+   #[serde(deserialize_with = "util::to_optional_f64")] pub qty: Option<f64>,
 
    /// Direction of trade - buy or sell
    pub side: OrderSide,
@@ -155,11 +195,20 @@ pub struct Order {
    //// Stop price
    #[serde(deserialize_with = "util::to_optional_f64")] pub stop_price: Option<f64>,
 
+   /// Timestamp the order was submitted at
+   #[serde(rename = "submitted_at")] pub submitted: Option<DateTime<Utc>>,
+
    /// Asset symbol
    pub symbol: String,
 
    /// how long the order is open for
    pub time_in_force: TimeInForce,
+
+   /// The percent a trailing stop order trails the high-water mark by
+   #[serde(default, deserialize_with = "util::to_optional_f64")] pub trail_percent: Option<f64>,
+
+   /// The dollar amount a trailing stop order trails the high-water mark by
+   #[serde(default, deserialize_with = "util::to_optional_f64")] pub trail_price: Option<f64>,
 }
 impl Order {
    /// Attempts to cancel this order. If the order is no longer cancelable (example: status=order_filled),
@@ -176,17 +225,38 @@ impl Order {
    /// open_order.cancel().await?;
    /// ```
    pub async fn cancel(&self, alpaca: &Alpaca) -> Result<()> {
-      let response = alpaca.request(Method::DELETE, format!("v2/orders/{}", self.id).as_str())?
-         .send().await.context(error::RequestFailed)?;
+      let response = alpaca.send(alpaca.request(Method::DELETE, format!("v2/orders/{}", self.id).as_str())?).await?;
 
-      if response.status().is_success() { return Ok(()) }
-      match response.status().as_u16() {
-         404 => error::OrderNotFound { order_id: self.id.clone() }.fail()?,
-         422 => error::OrderNotCancelable { order_id: self.id.clone() }.fail()?,
-         _ => error::Unknown.fail()?
+      let status = response.status().as_u16();
+      match status {
+         200..=299 | 404 | 422 => canceled(&self.id, status, error::ApiError::default()),
+         _ => Err(error::failed(response).await)?
       }
    }
 
+   /// Attempts to cancel all open orders in one call.  Returns the result of canceling each order, keyed by
+   /// order ID - an order that could not be canceled has the same error that `cancel` would give.
+   ///
+   /// # Example
+   ///
+   /// To cancel everything that is still open:
+   ///
+   /// ``` no run
+   /// let alpaca = Alpaca::live("KEY_ID", "SECRET").await.unwrap();
+   ///
+   /// for (order_id, result) in Order::cancel_all(&alpaca).await.unwrap() {
+   ///    if result.is_err() { println!("Could not cancel {}", order_id); }
+   /// }
+   /// ```
+   pub async fn cancel_all(alpaca: &Alpaca) -> Result<HashMap<String, Result<()>>> {
+      let response = alpaca.call(alpaca.request(Method::DELETE, "v2/orders")?).await?;
+
+      let canceled_orders = response.json::<Vec<CanceledOrder>>().await.context(error::BadData)?;
+      Ok(canceled_orders.into_iter()
+         .map(|order| { let result = canceled(&order.id, order.status, order.body.unwrap_or_default()); (order.id, result) })
+         .collect())
+   }
+
    /// Attempts to replace this open order.  Will fail if the order is not open or the user does not have
    /// enough buying power to execute it.
    ///
@@ -203,20 +273,85 @@ impl Order {
    ///    .place(&alpaca).await.unwrap();
    /// ```
    pub fn update(&self) -> OrderUpdater {
-      OrderUpdater { id: self.id.clone(), ..Default::default() }
+      let trails_by = match (self.trail_price, self.trail_percent) {
+         (Some(_), _) => Some(Trail::Price),
+         (_, Some(_)) => Some(Trail::Percent),
+         _ => None
+      };
+      OrderUpdater { id: self.id.clone(), trails_by, ..Default::default() }
+   }
+
+   /// Gets a single order by its order ID.
+   ///
+   /// # Example
+   ///
+   /// To get an order we placed earlier:
+   ///
+   /// ``` no run
+   /// let alpaca = Alpaca::live("KEY_ID", "SECRET").await.unwrap();
+   ///
+   /// let order = Order::get(&alpaca, "904837e3-3b76-47ec-b432-046db621571b").await.unwrap();
+   /// ```
+   pub async fn get(alpaca: &Alpaca, id: &str) -> Result<Order> {
+      let response = alpaca.send(alpaca.request(Method::GET, format!("v2/orders/{}", id).as_str())?).await?;
+
+      if response.status().is_success() { return Ok(response.json::<Order>().await.context(error::BadData)?) }
+      match response.status().as_u16() {
+         404 => error::OrderNotFound { order_id: id }.fail()?,
+         _ => Err(error::failed(response).await)?
+      }
+   }
+
+   /// Gets a single order by the client order ID it was placed with.
+   ///
+   /// # Example
+   ///
+   /// To find the order we placed with our own ID:
+   ///
+   /// ``` no run
+   /// let alpaca = Alpaca::live("KEY_ID", "SECRET").await.unwrap();
+   ///
+   /// let order = Order::get_by_client_order_id(&alpaca, "my-strategy-42").await.unwrap();
+   /// ```
+   pub async fn get_by_client_order_id(alpaca: &Alpaca, client_id: &str) -> Result<Order> {
+      let request = alpaca.request(Method::GET, "v2/orders:by_client_order_id")?
+         .query(&[("client_order_id", client_id)]);
+      let response = alpaca.send(request).await?;
+
+      if response.status().is_success() { return Ok(response.json::<Order>().await.context(error::BadData)?) }
+      match response.status().as_u16() {
+         404 => error::OrderNotFound { order_id: client_id }.fail()?,
+         _ => Err(error::failed(response).await)?
+      }
    }
 
    /// Gets a list of all open orders - returns an empty vector if there are no open orders
    pub async fn get_open(alpaca: &Alpaca) -> Result<Vec<Order>> {
-      let response = alpaca.request(Method::GET, "v2/orders")?
-         .query(&[("status", "open")])
-         .send().await.context(error::RequestFailed)?;
-
-      ensure!(response.status().is_success(), error::InvalidCredentials);
+      let request = alpaca.request(Method::GET, "v2/orders")?
+         .query(&[("status", "open")]);
+      let response = alpaca.call(request).await?;
 
       Ok(response.json::<Vec<Order>>().await.context(error::BadData)?)
    }
 
+   /// Starts a query over the order history.  Results are paged through automatically.
+   ///
+   /// # Example
+   ///
+   /// To get every AAPL order, open or closed, from the last 90 days:
+   ///
+   /// ``` no run
+   /// let alpaca = Alpaca::live("KEY_ID", "SECRET").await.unwrap();
+   ///
+   /// let orders: Vec<Order> = Order::query()
+   ///    .status(OrderQueryStatus::All)
+   ///    .after(Utc::now() - Duration::days(90))
+   ///    .symbols(&["AAPL"])
+   ///    .fetch(&alpaca)
+   ///    .try_collect().await.unwrap();
+   /// ```
+   pub fn query() -> OrderQuery { OrderQuery::default() }
+
    /// Requests a new 'buy' order.
    ///
    /// # Example
@@ -226,12 +361,28 @@ impl Order {
    /// ``` no run
    /// let alpaca = Alpaca::live("KEY_ID", "SECRET").await.unwrap();
    ///
-   /// let order = Order::buy("AAPL", 100, OrderType::Limit, TimeInForce::DAY)
+   /// let order = Order::buy("AAPL", 100.0, OrderType::Limit, TimeInForce::DAY)
    ///    .limit_price(100.0)
    ///    .place(&alpaca).await.unwrap();
    /// ```
-   pub fn buy(symbol: &str, qty: u32, order_type: OrderType, time_in_force: TimeInForce) -> OrderBuilder {
-      OrderBuilder { symbol: symbol.to_string(), qty: qty, side: OrderSide::Buy, order_type: order_type, time_in_force: time_in_force, ..Default::default() }
+   pub fn buy(symbol: &str, qty: f64, order_type: OrderType, time_in_force: TimeInForce) -> OrderBuilder {
+      OrderBuilder { symbol: symbol.to_string(), qty: Some(qty), side: OrderSide::Buy, order_type, time_in_force, ..Default::default() }
+   }
+
+   /// Requests a new 'buy' order for a dollar amount rather than a number of shares.
+   ///
+   /// # Example
+   ///
+   /// To buy $500.0 worth of AAPL at market price today:
+   ///
+   /// ``` no run
+   /// let alpaca = Alpaca::live("KEY_ID", "SECRET").await.unwrap();
+   ///
+   /// let order = Order::buy_notional("AAPL", 500.0, OrderType::Market, TimeInForce::DAY)
+   ///    .place(&alpaca).await.unwrap();
+   /// ```
+   pub fn buy_notional(symbol: &str, notional: f64, order_type: OrderType, time_in_force: TimeInForce) -> OrderBuilder {
+      OrderBuilder { symbol: symbol.to_string(), notional: Some(notional), side: OrderSide::Buy, order_type, time_in_force, ..Default::default() }
    }
 
    /// Requests a new 'sell' order.
@@ -243,11 +394,126 @@ impl Order {
    /// ``` no run
    /// let alpaca = Alpaca::live("KEY_ID", "SECRET").await.unwrap();
    ///
-   /// let order = Order::sell("MSFT", 100, OrderType::Market, TimeInForce::DAY)
+   /// let order = Order::sell("MSFT", 100.0, OrderType::Market, TimeInForce::DAY)
+   ///    .place(&alpaca).await.unwrap();
+   /// ```
+   pub fn sell(symbol: &str, qty: f64, order_type: OrderType, time_in_force: TimeInForce) -> OrderBuilder {
+      OrderBuilder { symbol: symbol.to_string(), qty: Some(qty), side: OrderSide::Sell, order_type, time_in_force, ..Default::default() }
+   }
+
+   /// Requests a new 'sell' order for a dollar amount rather than a number of shares.
+   ///
+   /// # Example
+   ///
+   /// To sell $500.0 worth of MSFT at market price today:
+   ///
+   /// ``` no run
+   /// let alpaca = Alpaca::live("KEY_ID", "SECRET").await.unwrap();
+   ///
+   /// let order = Order::sell_notional("MSFT", 500.0, OrderType::Market, TimeInForce::DAY)
    ///    .place(&alpaca).await.unwrap();
    /// ```
-   pub fn sell(symbol: &str, qty: u32, order_type: OrderType, time_in_force: TimeInForce) -> OrderBuilder {
-      OrderBuilder { symbol: symbol.to_string(), qty: qty, side: OrderSide::Sell, order_type: order_type, time_in_force: time_in_force, ..Default::default() }
+   pub fn sell_notional(symbol: &str, notional: f64, order_type: OrderType, time_in_force: TimeInForce) -> OrderBuilder {
+      OrderBuilder { symbol: symbol.to_string(), notional: Some(notional), side: OrderSide::Sell, order_type, time_in_force, ..Default::default() }
+   }
+}
+
+/// The result of canceling one order as part of canceling all of them
+#[derive(Debug, Deserialize)]
+struct CanceledOrder {
+   id: String,
+   status: u16,
+   #[serde(default)] body: Option<error::ApiError>
+}
+
+/// Maps the status of a cancel request to its result
+fn canceled(order_id: &str, status: u16, body: error::ApiError) -> Result<()> {
+   match status {
+      200..=299 => Ok(()),
+      404 => error::OrderNotFound { order_id }.fail()?,
+      422 => error::OrderNotCancelable { order_id }.fail()?,
+      _ => Err(error::failed_entry(status, body))?
+   }
+}
+
+/// The longest client order ID Alpaca accepts
+const MAX_CLIENT_ORDER_ID_LEN: usize = 48;
+
+/// Generates client order IDs.
+///
+/// Setting our own client order ID on an order makes placing it idempotent - Alpaca rejects a second
+/// order with the same ID.  Any closure returning a string can be used as a generator.
+pub trait ClientOrderIdGenerator {
+   /// Returns a new, unique ID of at most 48 characters
+   fn generate(&self) -> String;
+}
+impl<F: Fn() -> String> ClientOrderIdGenerator for F {
+   fn generate(&self) -> String { self() }
+}
+
+/// Generates client order IDs that start with a fixed prefix - such as a strategy name - followed by the
+/// current time and a counter.
+///
+/// # Example
+///
+/// To tag orders with the name of the strategy that placed them:
+///
+/// ``` no run
+/// let ids = PrefixedIdGenerator::new("momentum");
+///
+/// let order = Order::buy("AAPL", 100.0, OrderType::Market, TimeInForce::DAY)
+///    .generate_client_order_id(&ids)
+///    .place(&alpaca).await.unwrap();
+/// ```
+#[derive(Debug)]
+pub struct PrefixedIdGenerator {
+   prefix: String,
+   counter: AtomicU64
+}
+impl PrefixedIdGenerator {
+   /// Creates a new generator for the prefix
+   pub fn new(prefix: &str) -> PrefixedIdGenerator {
+      PrefixedIdGenerator { prefix: prefix.to_string(), counter: AtomicU64::new(0) }
+   }
+}
+impl ClientOrderIdGenerator for PrefixedIdGenerator {
+   fn generate(&self) -> String {
+      let count = self.counter.fetch_add(1, Ordering::Relaxed);
+      format!("{}-{}-{}", self.prefix, Utc::now().timestamp_millis(), count)
+   }
+}
+
+/// Checks that a client order ID is short enough for Alpaca to accept
+fn validate_client_order_id(client_order_id: &Option<String>) -> Result<()> {
+   match client_order_id {
+      Some(id) if id.len() > MAX_CLIENT_ORDER_ID_LEN => {
+         error::OrderInvalid { reason: format!("Client order IDs can be at most {} characters.", MAX_CLIENT_ORDER_ID_LEN) }.fail()?
+      },
+      _ => Ok(())
+   }
+}
+
+/// Works out why Alpaca rejected an order.  If the client order ID was already used then the order
+/// that has it is looked up and returned as part of the error - or returned as the order that was placed,
+/// if an earlier try of this call may have placed it.
+async fn rejected(alpaca: &Alpaca, client_order_id: &Option<String>, response: Response, retried: bool) -> Result<Order> {
+   match response.status().as_u16() {
+      403 => {
+         let body = error::ApiError::from_response(response).await;
+         error::OrderForbidden { code: body.code, message: body.message }.fail()?
+      },
+      422 => {
+         let error = error::failed(response).await;
+         match (client_order_id, error) {
+            (Some(id), error::InnerError::Invalid { code, message }) if error::is_duplicate_client_order_id(code, &message) => {
+               let order = Order::get_by_client_order_id(alpaca, id).await?;
+               if retried { return Ok(order) }
+               error::DuplicateClientOrderId { client_order_id: id, order: Box::new(order) }.fail()?
+            },
+            (_, error) => Err(error)?
+         }
+      },
+      _ => Err(error::failed(response).await)?
    }
 }
 
@@ -256,6 +522,9 @@ impl Order {
 /// This structure is not create directly - but is returned from Order.buy or Order.sell
 #[derive(Debug, Serialize)]
 pub struct OrderBuilder {
+   /// A unique identifier for the order - generated by Alpaca if not set
+   #[serde(skip_serializing_if = "Option::is_none")] client_order_id: Option<String>,
+
    /// Defaults to false; if true the order will be eligible to execute in premarket/afterhours.
    /// Only valid with order_type of Limit and time_in_force of DAY.
    extended_hours: bool,
@@ -263,25 +532,55 @@ pub struct OrderBuilder {
    /// Required if the order_type is Limit or StopLimit
    #[serde(skip_serializing_if = "Option::is_none", serialize_with = "util::to_optional_string")] limit_price: Option<f64>,
 
+   /// The class of the order - defaults to Simple
+   order_class: OrderClass,
+
+   /// The dollar amount to trade - set instead of the number of shares
+   #[serde(skip_serializing_if = "Option::is_none", serialize_with = "util::to_optional_string")] notional: Option<f64>,
+
    /// The type of the order
    #[serde(rename(serialize="type"))] order_type: OrderType,
 
-   /// Number of shares to trade
-   #[serde(serialize_with = "util::to_string")] qty: u32,
+   /// Number of shares to trade - which may be fractional
+   #[serde(skip_serializing_if = "Option::is_none", serialize_with = "util::to_optional_string")] qty: Option<f64>,
 
    /// The side of the trade - buy or sell
    side: OrderSide,
 
+   /// The stop loss exit leg of a bracket, OCO or OTO order
+   #[serde(skip_serializing_if = "Option::is_none")] stop_loss: Option<StopLoss>,
+
    /// Required if order_type is Stop or StopLimit
    #[serde(skip_serializing_if = "Option::is_none", serialize_with = "util::to_optional_string")] stop_price: Option<f64>,
 
    /// Symbol or asset ID to identify the asset to trade
    symbol: String,
 
+   /// The take profit exit leg of a bracket, OCO or OTO order
+   #[serde(skip_serializing_if = "Option::is_none")] take_profit: Option<TakeProfit>,
+
    /// How long the order will stay in effect
    time_in_force: TimeInForce,
+
+   /// The percent a trailing stop order trails the market by
+   #[serde(skip_serializing_if = "Option::is_none", serialize_with = "util::to_optional_string")] trail_percent: Option<f64>,
+
+   /// The dollar amount a trailing stop order trails the market by
+   #[serde(skip_serializing_if = "Option::is_none", serialize_with = "util::to_optional_string")] trail_price: Option<f64>,
 }
 impl OrderBuilder {
+   /// Sets our own unique identifier for the order
+   pub fn client_order_id(mut self, client_order_id: &str) -> OrderBuilder {
+      self.client_order_id = Some(client_order_id.to_string());
+      self
+   }
+
+   /// Sets the order's unique identifier to one created by the generator
+   pub fn generate_client_order_id<G: ClientOrderIdGenerator + ?Sized>(mut self, generator: &G) -> OrderBuilder {
+      self.client_order_id = Some(generator.generate());
+      self
+   }
+
    /// Sets the extended hours flag
    pub fn extended_hours(mut self, extended_hours: bool) -> OrderBuilder {
       self.extended_hours = extended_hours;
@@ -294,58 +593,200 @@ impl OrderBuilder {
       self
    }
 
+   /// Sets the class of the order
+   pub fn order_class(mut self, order_class: OrderClass) -> OrderBuilder {
+      self.order_class = order_class;
+      self
+   }
+
+   /// Sets the stop loss exit leg for a bracket, OCO or OTO order
+   pub fn stop_loss(mut self, stop_loss: StopLoss) -> OrderBuilder {
+      self.stop_loss = Some(stop_loss);
+      self
+   }
+
    /// Sets the stop price
    pub fn stop_price(mut self, stop_price: f64) -> OrderBuilder {
       self.stop_price = Some(stop_price);
       self
    }
 
+   /// Sets the take profit exit leg for a bracket, OCO or OTO order
+   pub fn take_profit(mut self, take_profit: TakeProfit) -> OrderBuilder {
+      self.take_profit = Some(take_profit);
+      self
+   }
+
+   /// Sets the percent a trailing stop order trails the market by
+   pub fn trail_percent(mut self, trail_percent: f64) -> OrderBuilder {
+      self.trail_percent = Some(trail_percent);
+      self
+   }
+
+   /// Sets the dollar amount a trailing stop order trails the market by
+   pub fn trail_price(mut self, trail_price: f64) -> OrderBuilder {
+      self.trail_price = Some(trail_price);
+      self
+   }
+
    /// Attempts to place the order.  Will fail if certain preconditions aren't met, including:
    ///  * Limit order with no limit price
    ///  * Stop order with no stop price
+   ///  * Trailing stop order without exactly one of a trail price or trail percent
+   ///  * Fractional or notional orders that are not market orders for today
    ///  * Extended hours requested for non limit orders where time_in_force is not Day
-   //
-   //  Will also fail if the buying power or shares are not sufficient.
+   ///  * A client order ID longer than 48 characters
+   ///  * Legs that don't match the order class, or exit prices on the wrong side of the entry
+   ///
+   /// Will also fail if the buying power or shares are not sufficient.  If the client order ID has already
+   /// been used the error carries the existing order - see `Error::existing_order`.
+   ///
+   /// Orders with a client order ID are retried on temporary failures.  If an earlier try did reach Alpaca,
+   /// the retry is rejected as a duplicate and the order that was placed is returned.
    pub async fn place(&self, alpaca: &Alpaca) -> Result<Order> {
       // pre-conditions
-      if (self.order_type == OrderType::Limit || self.order_type == OrderType::StopLimit) && self.limit_price == None {
+      validate_client_order_id(&self.client_order_id)?;
+      if (self.order_type == OrderType::Limit || self.order_type == OrderType::StopLimit) && self.limit_price.is_none() {
          error::OrderInvalid { reason: "Limit orders need a limit price.".to_string() }.fail()?
       }
-      if (self.order_type == OrderType::Stop || self.order_type == OrderType::StopLimit) && self.stop_price == None {
+      if (self.order_type == OrderType::Stop || self.order_type == OrderType::StopLimit) && self.stop_price.is_none() {
          error::OrderInvalid { reason: "Stop orders need a stop price.".to_string() }.fail()?
       }
+      match (self.qty, self.notional) {
+         (Some(qty), None) if qty > 0.0 => {},
+         (None, Some(notional)) if notional > 0.0 => {},
+         _ => error::OrderInvalid { reason: "Orders need a positive quantity or notional amount.".to_string() }.fail()?
+      }
+      let is_fractional = self.notional.is_some() || self.qty.is_some_and(|qty| qty.fract() != 0.0);
+      if is_fractional && (self.order_type != OrderType::Market || self.time_in_force != TimeInForce::DAY) {
+         error::OrderInvalid { reason: "Fractional and notional orders must be market orders for today.".to_string() }.fail()?
+      }
+      match (&self.order_type, self.trail_price, self.trail_percent) {
+         (OrderType::TrailingStop, Some(_), Some(_)) | (OrderType::TrailingStop, None, None) => {
+            error::OrderInvalid { reason: "Trailing stop orders need either a trail price or a trail percent.".to_string() }.fail()?
+         },
+         (OrderType::TrailingStop, _, _) | (_, None, None) => {},
+         _ => error::OrderInvalid { reason: "Only trailing stop orders can have a trail price or trail percent.".to_string() }.fail()?
+      }
       if self.extended_hours && (self.order_type != OrderType::Limit && self.time_in_force != TimeInForce::DAY) {
          error::OrderInvalid { reason: "Extended hours only works with limit orders for today".to_string() }.fail()?
       }
+      self.validate_legs()?;
 
-      let response = alpaca.request(Method::POST, "v2/orders")?
-         .json::<OrderBuilder>(self)
-         .send()
-         .await.context(error::BadData)?;
+      let request = alpaca.request(Method::POST, "v2/orders")?
+         .json::<OrderBuilder>(self);
+      let (response, retried) = alpaca.send_retried(request).await?;
 
       if response.status().is_success() { return Ok(response.json::<Order>().await.context(error::BadData)?) }
 
-      match response.status().as_u16() {
-         403 => error::OrderForbidden.fail()?,
-         _ => error::Unknown.fail()?
+      rejected(alpaca, &self.client_order_id, response, retried).await
+   }
+
+   /// Checks that the legs match the order class, and that the exit prices make sense for the side
+   fn validate_legs(&self) -> Result<()> {
+      let has_legs = (self.take_profit.is_some(), self.stop_loss.is_some());
+      match (&self.order_class, has_legs) {
+         (OrderClass::Simple, (false, false)) => return Ok(()),
+         (OrderClass::Simple, _) => error::OrderInvalid { reason: "Simple orders cannot have take profit or stop loss legs.".to_string() }.fail()?,
+         (OrderClass::Bracket, (true, true)) | (OrderClass::Oco, (true, true)) => {},
+         (OrderClass::Bracket, _) | (OrderClass::Oco, _) => {
+            error::OrderInvalid { reason: "Bracket and OCO orders need both a take profit and a stop loss.".to_string() }.fail()?
+         },
+         (OrderClass::Oto, (true, false)) | (OrderClass::Oto, (false, true)) => {},
+         (OrderClass::Oto, _) => error::OrderInvalid { reason: "OTO orders need exactly one of a take profit or a stop loss.".to_string() }.fail()?
+      }
+      if self.order_class == OrderClass::Oco && self.order_type != OrderType::Limit {
+         error::OrderInvalid { reason: "OCO orders must be limit orders.".to_string() }.fail()?
+      }
+      if self.time_in_force != TimeInForce::DAY && self.time_in_force != TimeInForce::GTC {
+         error::OrderInvalid { reason: "Orders with legs must be either DAY or GTC.".to_string() }.fail()?
+      }
+
+      // The exits of an OCO order are the order itself; otherwise they are on the other side of the entry.
+      // Either way, exits that close a long position take profit above where they stop the loss.
+      let closes_long = (self.side == OrderSide::Buy) != (self.order_class == OrderClass::Oco);
+      let above = |high: f64, low: f64| if closes_long { high > low } else { high < low };
+      let take_profit = self.take_profit.as_ref().map(|leg| leg.limit_price);
+      let stop = self.stop_loss.as_ref().map(|leg| leg.stop_price);
+      let entry = if self.order_class == OrderClass::Oco { None } else { self.limit_price };
+
+      if let (Some(take_profit), Some(stop)) = (take_profit, stop) {
+         ensure!(above(take_profit, stop), error::OrderInvalid { reason: "The take profit price must be beyond the stop loss price." });
+      }
+      if let (Some(take_profit), Some(entry)) = (take_profit, entry) {
+         ensure!(above(take_profit, entry), error::OrderInvalid { reason: "The take profit price must be beyond the limit price." });
       }
+      if let (Some(stop), Some(entry)) = (stop, entry) {
+         ensure!(above(entry, stop), error::OrderInvalid { reason: "The stop loss price must be behind the limit price." });
+      }
+      if let Some(StopLoss { stop_price, limit_price: Some(limit_price) }) = self.stop_loss {
+         ensure!(!above(limit_price, stop_price), error::OrderInvalid { reason: "The stop loss limit price must not be beyond its stop price." });
+      }
+      Ok(())
    }
 }
 impl Default for OrderBuilder {
    fn default() -> Self {
       OrderBuilder {
+         client_order_id: None,
          symbol: "".to_string(),
          extended_hours: false,
-         qty: 0,
+         notional: None,
+         qty: None,
          side: OrderSide::Buy,
          order_type: OrderType::Market,
          time_in_force: TimeInForce::DAY,
          limit_price: None,
-         stop_price: None
+         order_class: OrderClass::Simple,
+         stop_loss: None,
+         stop_price: None,
+         take_profit: None,
+         trail_percent: None,
+         trail_price: None
       }
    }
 }
 
+/// The take profit exit leg of a bracket, OCO or OTO order.
+#[derive(Debug, Serialize)]
+pub struct TakeProfit {
+   /// The limit price the position is closed at
+   #[serde(serialize_with = "util::to_string")] limit_price: f64
+}
+impl TakeProfit {
+   /// Creates a take profit leg that closes the position at the limit price
+   pub fn new(limit_price: f64) -> TakeProfit { TakeProfit { limit_price } }
+}
+
+/// The stop loss exit leg of a bracket, OCO or OTO order.
+///
+/// By default the stop loss is a stop order - setting a limit price makes it a stop limit order.
+#[derive(Debug, Serialize)]
+pub struct StopLoss {
+   /// The price that triggers closing the position
+   #[serde(serialize_with = "util::to_string")] stop_price: f64,
+
+   /// The limit price once the stop is triggered
+   #[serde(skip_serializing_if = "Option::is_none", serialize_with = "util::to_optional_string")] limit_price: Option<f64>
+}
+impl StopLoss {
+   /// Creates a stop loss leg that closes the position at market once the stop price is hit
+   pub fn new(stop_price: f64) -> StopLoss { StopLoss { stop_price, limit_price: None } }
+
+   /// Sets the limit price - making the stop loss a stop limit order
+   pub fn limit_price(mut self, limit_price: f64) -> StopLoss {
+      self.limit_price = Some(limit_price);
+      self
+   }
+}
+
+/// How a trailing stop order trails the market
+#[derive(Debug, PartialEq)]
+enum Trail {
+   Percent,
+   Price
+}
+
 /// Builds up a replacement order and has the logic to submit it.
 ///
 /// This structure is not create directly - but is returned from Order.update
@@ -354,27 +795,51 @@ pub struct OrderUpdater {
    /// The id of the order to replace
    #[serde(skip_serializing)] id: String,
 
+   /// A new unique identifier for the replacement order
+   #[serde(skip_serializing_if = "Option::is_none")] client_order_id: Option<String>,
+
    /// Required if the order_type is Limit or StopLimit
    #[serde(skip_serializing_if = "Option::is_none", serialize_with = "util::to_optional_string")] limit_price: Option<f64>,
 
    /// The number of shares to trade
-   #[serde(skip_serializing_if = "Option::is_none", serialize_with = "util::to_optional_string")] qty: Option<u32>,
+   #[serde(skip_serializing_if = "Option::is_none", serialize_with = "util::to_optional_string")] qty: Option<f64>,
 
    /// Required if order_type is Stop or StopLimit
    #[serde(skip_serializing_if = "Option::is_none", serialize_with = "util::to_optional_string")] stop_price: Option<f64>,
 
    /// How long the order will stay in effect
    #[serde(skip_serializing_if = "Option::is_none", serialize_with = "util::to_optional_string")] time_in_force: Option<TimeInForce>,
+
+   /// The new trail of a trailing stop order - Alpaca applies it to whichever of price or percent the order trails by
+   #[serde(skip_serializing_if = "Option::is_none", serialize_with = "util::to_optional_string")] trail: Option<f64>,
+
+   /// What the order being replaced trails by - if it is a trailing stop order
+   #[serde(skip_serializing)] trails_by: Option<Trail>,
+
+   /// What the new trail was set as
+   #[serde(skip_serializing)] trail_set_as: Option<Trail>,
 }
 impl OrderUpdater {
+   /// Sets our own unique identifier for the replacement order
+   pub fn client_order_id(mut self, client_order_id: &str) -> OrderUpdater {
+      self.client_order_id = Some(client_order_id.to_string());
+      self
+   }
+
+   /// Sets the replacement order's unique identifier to one created by the generator
+   pub fn generate_client_order_id<G: ClientOrderIdGenerator + ?Sized>(mut self, generator: &G) -> OrderUpdater {
+      self.client_order_id = Some(generator.generate());
+      self
+   }
+
    /// Sets the price limit
    pub fn limit_price(mut self, limit_price: f64) -> OrderUpdater {
       self.limit_price = Some(limit_price);
       self
    }
 
-   /// Sets the number of shares to trade
-   pub fn qty(mut self, qty: u32) -> OrderUpdater {
+   /// Sets the number of shares to trade - which may be fractional
+   pub fn qty(mut self, qty: f64) -> OrderUpdater {
       self.qty = Some(qty);
       self
    }
@@ -391,18 +856,196 @@ impl OrderUpdater {
       self
    }
 
-   /// Attempts to replace the order.
+   /// Sets the new percent for a trailing stop order placed with a trail percent
+   pub fn trail_percent(mut self, trail_percent: f64) -> OrderUpdater {
+      self.trail = Some(trail_percent);
+      self.trail_set_as = Some(Trail::Percent);
+      self
+   }
+
+   /// Sets the new dollar amount for a trailing stop order placed with a trail price
+   pub fn trail_price(mut self, trail_price: f64) -> OrderUpdater {
+      self.trail = Some(trail_price);
+      self.trail_set_as = Some(Trail::Price);
+      self
+   }
+
+   /// Attempts to replace the order.  Will fail if a trail is set that doesn't match how the order trails.
    pub async fn place(&self, alpaca: &Alpaca) -> Result<Order> {
-      let response = alpaca.request(Method::PATCH, format!("v2/orders/{}", self.id).as_str())?
-         .json::<OrderUpdater>(self)
-         .send()
-         .await.context(error::BadData)?;
+      validate_client_order_id(&self.client_order_id)?;
+      if self.trail_set_as.is_some() && self.trail_set_as != self.trails_by {
+         error::OrderInvalid { reason: "The trail must be updated the same way the order trails - by price or by percent.".to_string() }.fail()?
+      }
 
-      if response.status().is_success() { return Ok(response.json::<Order>().await.context(error::BadData)?) }
+      let request = alpaca.request(Method::PATCH, format!("v2/orders/{}", self.id).as_str())?
+         .json::<OrderUpdater>(self);
+      let response = alpaca.send(request).await?;
 
+      if response.status().is_success() { return Ok(response.json::<Order>().await.context(error::BadData)?) }
       match response.status().as_u16() {
-         403 => error::OrderForbidden.fail()?,
-         _ => error::Unknown.fail()?
+         404 => error::OrderNotFound { order_id: self.id.as_str() }.fail()?,
+         _ => rejected(alpaca, &self.client_order_id, response, false).await
       }
-   }   
-}
\ No newline at end of file
+   }
+}
+
+/// The status of the orders to query for
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OrderQueryStatus {
+   /// Both open and closed orders
+   All,
+
+   /// Orders that are no longer open - filled, canceled, expired, etc.
+   Closed,
+
+   /// Orders that are still open
+   Open
+}
+
+/// The order that query results are returned in, by submission time
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortDirection {
+   /// Oldest orders first
+   Asc,
+
+   /// Newest orders first
+   Desc
+}
+
+/// Builds up a query over the order history and pages through the results.
+///
+/// This structure is not create directly - but is returned from Order.query
+#[derive(Clone, Debug, Serialize)]
+pub struct OrderQuery {
+   /// Which orders to return - defaults to open orders
+   status: OrderQueryStatus,
+
+   /// The maximum number of orders to get in each page
+   limit: u32,
+
+   /// Only orders submitted after this time (exclusive)
+   #[serde(skip_serializing_if = "Option::is_none")] after: Option<DateTime<Utc>>,
+
+   /// Only orders submitted until this time (exclusive)
+   #[serde(skip_serializing_if = "Option::is_none")] until: Option<DateTime<Utc>>,
+
+   /// The order of the results - defaults to newest first
+   direction: SortDirection,
+
+   /// If true, the legs of multi-leg orders are rolled up under their parent order
+   nested: bool,
+
+   /// Comma separated list of symbols to limit the results to
+   #[serde(skip_serializing_if = "Option::is_none")] symbols: Option<String>,
+}
+impl OrderQuery {
+   /// The most orders Alpaca will return in one call
+   const MAX_PAGE_SIZE: u32 = 500;
+
+   /// Sets which orders to return - open, closed or all
+   pub fn status(mut self, status: OrderQueryStatus) -> OrderQuery {
+      self.status = status;
+      self
+   }
+
+   /// Only returns orders submitted after this time
+   pub fn after(mut self, after: DateTime<Utc>) -> OrderQuery {
+      self.after = Some(after);
+      self
+   }
+
+   /// Only returns orders submitted before this time
+   pub fn until(mut self, until: DateTime<Utc>) -> OrderQuery {
+      self.until = Some(until);
+      self
+   }
+
+   /// Only returns orders for these symbols
+   pub fn symbols(mut self, symbols: &[&str]) -> OrderQuery {
+      self.symbols = Some(symbols.join(","));
+      self
+   }
+
+   /// Sets the order the results are returned in
+   pub fn direction(mut self, direction: SortDirection) -> OrderQuery {
+      self.direction = direction;
+      self
+   }
+
+   /// Sets whether the legs of multi-leg orders are rolled up under their parent order
+   pub fn nested(mut self, nested: bool) -> OrderQuery {
+      self.nested = nested;
+      self
+   }
+
+   /// Sets how many orders are requested from Alpaca at a time - at most 500
+   pub fn page_size(mut self, page_size: u32) -> OrderQuery {
+      self.limit = page_size.clamp(1, OrderQuery::MAX_PAGE_SIZE);
+      self
+   }
+
+   /// Runs the query, returning a stream of all of the matching orders.
+   ///
+   /// Pages are requested as the stream is consumed, moving the time window past the last order of
+   /// each page.  Orders that show up in two pages because they share a timestamp are only returned
+   /// once.
+   pub fn fetch<'a>(&self, alpaca: &'a Alpaca) -> impl Stream<Item = Result<Order>> + 'a {
+      let pages = stream::try_unfold(Some((self.clone(), HashSet::new())), move |state| async move {
+         match state {
+            Some((query, seen)) => query.next_page(alpaca, seen).await.map(Some),
+            None => Ok(None)
+         }
+      });
+
+      pages
+         .map_ok(|orders| stream::iter(orders.into_iter().map(Ok)))
+         .try_flatten()
+   }
+
+   /// Gets the next page of results, skipping the orders already seen on the previous page.  Returns the
+   /// new orders and the query for the page after - if there is one.
+   async fn next_page(mut self, alpaca: &Alpaca, seen: HashSet<String>) -> Result<(Vec<Order>, Option<(OrderQuery, HashSet<String>)>)> {
+      let page = self.fetch_page(alpaca).await?;
+      let is_full = page.len() >= self.limit as usize;
+      let orders: Vec<Order> = page.into_iter().filter(|order| !seen.contains(&order.id)).collect();
+
+      // a short page is the last one - and a full page with nothing new means we can't move forward
+      let last = match orders.last() {
+         Some(last) if is_full => last.submitted.unwrap_or(last.created),
+         _ => return Ok((orders, None))
+      };
+
+      // move the window so that it still includes the last timestamp - we filter out what we've seen
+      match self.direction {
+         SortDirection::Asc => self.after = Some(last - Duration::microseconds(1)),
+         SortDirection::Desc => self.until = Some(last + Duration::microseconds(1))
+      }
+      let seen = orders.iter().map(|order| order.id.clone()).collect();
+
+      Ok((orders, Some((self, seen))))
+   }
+
+   /// Gets a single page of results
+   async fn fetch_page(&self, alpaca: &Alpaca) -> Result<Vec<Order>> {
+      let request = alpaca.request(Method::GET, "v2/orders")?
+         .query(self);
+      let response = alpaca.call(request).await?;
+
+      Ok(response.json::<Vec<Order>>().await.context(error::BadData)?)
+   }
+}
+impl Default for OrderQuery {
+   fn default() -> Self {
+      OrderQuery {
+         status: OrderQueryStatus::Open,
+         limit: OrderQuery::MAX_PAGE_SIZE,
+         after: None,
+         until: None,
+         direction: SortDirection::Desc,
+         nested: false,
+         symbols: None
+      }
+   }
+}
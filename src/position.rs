@@ -0,0 +1,175 @@
+use reqwest::Method;
+use serde::Deserialize;
+use snafu::ResultExt;
+use std::collections::HashMap;
+
+use crate::{ error, util, Alpaca, Order, Result };
+
+/// The side of the position - long or short
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PositionSide {
+   /// A position that profits when the price goes up
+   Long,
+
+   /// A position that profits when the price goes down
+   Short
+}
+
+/// An open position in an asset.
+///
+/// Positions are created when orders fill and are updated as further orders on the same asset are
+/// filled.  The position is removed once its quantity reaches zero.
+#[derive(Debug, Deserialize)]
+pub struct Position {
+   /// Asset ID - a UUID
+   pub asset_id: String,
+
+   /// Asset symbol
+   pub symbol: String,
+
+   /// Exchange name of the asset
+   pub exchange: String,
+
+   /// Asset class name
+   pub asset_class: String,
+
+   /// Average entry price of the position
+   #[serde(deserialize_with = "util::to_f64")] pub avg_entry_price: f64,
+
+   /// The number of shares - which may be fractional
+   #[serde(deserialize_with = "util::to_f64")] pub qty: f64,
+
+   /// Direction of the position - long or short
+   pub side: PositionSide,
+
+   /// Total dollar amount of the position
+   #[serde(deserialize_with = "util::to_f64")] pub market_value: f64,
+
+   /// Total cost basis in dollars
+   #[serde(deserialize_with = "util::to_f64")] pub cost_basis: f64,
+
+   /// Unrealized profit/loss in dollars
+   #[serde(rename = "unrealized_pl", deserialize_with = "util::to_f64")] pub unrealized_profit: f64,
+
+   /// Unrealized profit/loss percent (by a factor of 1)
+   #[serde(rename = "unrealized_plpc", deserialize_with = "util::to_f64")] pub unrealized_profit_percent: f64,
+
+   /// Unrealized profit/loss in dollars for the day
+   #[serde(rename = "unrealized_intraday_pl", deserialize_with = "util::to_f64")] pub unrealized_intraday_profit: f64,
+
+   /// Unrealized profit/loss percent for the day (by a factor of 1)
+   #[serde(rename = "unrealized_intraday_plpc", deserialize_with = "util::to_f64")] pub unrealized_intraday_profit_percent: f64,
+
+   /// Current asset price per share
+   #[serde(deserialize_with = "util::to_f64")] pub current_price: f64,
+
+   /// Last day’s asset price per share based on the closing value of the last trading day
+   #[serde(rename = "lastday_price", deserialize_with = "util::to_f64")] pub last_day_price: f64,
+
+   /// Percent change from last day price (by a factor of 1)
+   #[serde(deserialize_with = "util::to_f64")] pub change_today: f64,
+}
+
+/// The result of closing one position as part of closing all of them.
+#[derive(Debug, Deserialize)]
+struct ClosedPosition {
+   symbol: String,
+   status: u16,
+   body: serde_json::Value
+}
+impl ClosedPosition {
+   /// Extracts the order that closed the position, or the reason it could not be closed
+   fn into_order(self) -> Result<Order> {
+      match self.status {
+         200..=299 => Ok(serde_json::from_value::<Order>(self.body).context(error::InternalJSON)?),
+         404 => error::PositionNotFound { symbol: self.symbol }.fail()?,
+         422 => error::PositionNotClosable { symbol: self.symbol }.fail()?,
+         status => Err(error::failed_entry(status, serde_json::from_value(self.body).unwrap_or_default()))?
+      }
+   }
+}
+
+impl Position {
+   /// Gets a list of all open positions - returns an empty vector if there are no open positions
+   ///
+   /// # Example
+   ///
+   /// To get all of the open positions:
+   ///
+   /// ``` no run
+   /// let alpaca = Alpaca::live("KEY_ID", "SECRET").await.unwrap();
+   ///
+   /// let positions = Position::get_all(&alpaca).await.unwrap();
+   /// ```
+   pub async fn get_all(alpaca: &Alpaca) -> Result<Vec<Position>> {
+      let response = alpaca.call(alpaca.request(Method::GET, "v2/positions")?).await?;
+
+      Ok(response.json::<Vec<Position>>().await.context(error::BadData)?)
+   }
+
+   /// Gets the open position for a single symbol.  Fails if there is no open position for the symbol.
+   ///
+   /// # Example
+   ///
+   /// To get the open position in AAPL:
+   ///
+   /// ``` no run
+   /// let alpaca = Alpaca::live("KEY_ID", "SECRET").await.unwrap();
+   ///
+   /// let position = Position::get(&alpaca, "AAPL").await.unwrap();
+   /// ```
+   pub async fn get(alpaca: &Alpaca, symbol: &str) -> Result<Position> {
+      let response = alpaca.send(alpaca.request(Method::GET, format!("v2/positions/{}", symbol).as_str())?).await?;
+
+      if response.status().is_success() { return Ok(response.json::<Position>().await.context(error::BadData)?) }
+      match response.status().as_u16() {
+         404 => error::PositionNotFound { symbol }.fail()?,
+         _ => Err(error::failed(response).await)?
+      }
+   }
+
+   /// Closes (liquidates) the open position for a single symbol at market price.  Returns the order
+   /// that was placed to close the position.
+   ///
+   /// # Example
+   ///
+   /// To close the open position in AAPL:
+   ///
+   /// ``` no run
+   /// let alpaca = Alpaca::live("KEY_ID", "SECRET").await.unwrap();
+   ///
+   /// let order = Position::close(&alpaca, "AAPL").await.unwrap();
+   /// ```
+   pub async fn close(alpaca: &Alpaca, symbol: &str) -> Result<Order> {
+      let response = alpaca.send(alpaca.request(Method::DELETE, format!("v2/positions/{}", symbol).as_str())?).await?;
+
+      if response.status().is_success() { return Ok(response.json::<Order>().await.context(error::BadData)?) }
+      match response.status().as_u16() {
+         404 => error::PositionNotFound { symbol }.fail()?,
+         422 => error::PositionNotClosable { symbol }.fail()?,
+         _ => Err(error::failed(response).await)?
+      }
+   }
+
+   /// Closes (liquidates) all open positions at market price.  Returns the result of closing each
+   /// position, keyed by symbol.
+   ///
+   /// # Example
+   ///
+   /// To close all open positions:
+   ///
+   /// ``` no run
+   /// let alpaca = Alpaca::live("KEY_ID", "SECRET").await.unwrap();
+   ///
+   /// for (symbol, result) in Position::close_all(&alpaca).await.unwrap() {
+   ///    if result.is_err() { println!("Could not close {}", symbol); }
+   /// }
+   /// ```
+   pub async fn close_all(alpaca: &Alpaca) -> Result<HashMap<String, Result<Order>>> {
+      let response = alpaca.call(alpaca.request(Method::DELETE, "v2/positions")?).await?;
+
+      let closed = response.json::<Vec<ClosedPosition>>().await.context(error::BadData)?;
+      Ok(closed.into_iter().map(|closed| (closed.symbol.clone(), closed.into_order())).collect())
+   }
+}
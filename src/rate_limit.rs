@@ -0,0 +1,168 @@
+use chrono::{ DateTime, TimeZone, Utc };
+use reqwest::{ Method, Response };
+use std::sync::Mutex;
+use std::time::{ Duration, Instant };
+use tokio::time::delay_for;
+
+const LIMIT_HEADER: &str = "X-RateLimit-Limit";
+const REMAINING_HEADER: &str = "X-RateLimit-Remaining";
+const RESET_HEADER: &str = "X-RateLimit-Reset";
+
+/// The shortest time to wait before checking the bucket again
+const MIN_WAIT: Duration = Duration::from_millis(1);
+
+/// Where the API key stands against Alpaca's rate limit, as of the last response.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RateLimitStatus {
+   /// The number of calls allowed in the current window
+   pub limit: u32,
+
+   /// The number of calls left in the current window
+   pub remaining: u32,
+
+   /// When the current window ends and the remaining calls are reset
+   pub reset: DateTime<Utc>
+}
+impl RateLimitStatus {
+   /// Reads the rate limit headers from a response - if they are all there and make sense
+   pub(crate) fn from_response(response: &Response) -> Option<RateLimitStatus> {
+      let header = |name: &str| response.headers().get(name)?.to_str().ok().map(str::trim);
+
+      Some(RateLimitStatus {
+         limit: header(LIMIT_HEADER)?.parse::<u32>().ok()?,
+         remaining: header(REMAINING_HEADER)?.parse::<u32>().ok()?,
+         reset: Utc.timestamp_opt(header(RESET_HEADER)?.parse::<i64>().ok()?, 0).single()?
+      })
+   }
+}
+
+/// A client side limit on how fast calls are sent to Alpaca.
+///
+/// Calls over the limit wait in the client rather than being sent and rejected with a 429.  The limit is
+/// a token bucket - up to `requests` calls can be made in a burst, and the bucket refills evenly over
+/// `period`.  The bucket is also kept in line with what Alpaca says is remaining, so that several
+/// processes sharing a key slow down together.
+///
+/// Cancels (DELETE calls) are sent ahead of any other waiting calls, so that risk can always be taken off
+/// even when the limit has been reached.
+///
+/// # Example
+///
+/// To stay within Alpaca's default limit of 200 calls a minute:
+///
+/// ``` no run
+/// let alpaca = AlpacaBuilder::live("KEY_ID", "SECRET")
+///    .rate_limiter(RateLimiter::new(200, Duration::from_secs(60)))
+///    .build().await.unwrap();
+/// ```
+#[derive(Clone, Debug)]
+pub struct RateLimiter {
+   requests: u32,
+   period: Duration
+}
+impl RateLimiter {
+   /// Creates a limit of `requests` calls every `period`
+   pub fn new(requests: u32, period: Duration) -> RateLimiter {
+      RateLimiter { requests: requests.max(1), period }
+   }
+}
+
+/// How urgently a call needs to be sent
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Priority {
+   /// Cancels and closes - these take risk off and go first
+   Cancel,
+
+   /// Everything else
+   Normal
+}
+impl Priority {
+   /// Works out the priority of a call from its method
+   pub(crate) fn of(method: &Method) -> Priority {
+      if *method == Method::DELETE { Priority::Cancel } else { Priority::Normal }
+   }
+}
+
+/// The current state of the token bucket
+#[derive(Debug)]
+struct Bucket {
+   tokens: f64,
+   updated: Instant,
+   waiting_cancels: usize,
+   blocked_until: Option<Instant>
+}
+
+/// The token bucket behind a rate limiter, shared by every call made with an Alpaca context
+#[derive(Debug)]
+pub(crate) struct Limiter {
+   capacity: f64,
+   per_second: f64,
+   bucket: Mutex<Bucket>
+}
+impl Limiter {
+   pub(crate) fn new(config: &RateLimiter) -> Limiter {
+      let capacity = f64::from(config.requests);
+      Limiter {
+         capacity,
+         per_second: capacity / config.period.as_secs_f64().max(f64::EPSILON),
+         bucket: Mutex::new(Bucket { tokens: capacity, updated: Instant::now(), waiting_cancels: 0, blocked_until: None })
+      }
+   }
+
+   /// Waits until a call with the given priority can be sent
+   pub(crate) async fn acquire(&self, priority: Priority) {
+      let mut waiting = None;
+      loop {
+         let wait = {
+            let mut bucket = self.bucket.lock().unwrap();
+            self.refill(&mut bucket);
+
+            let now = Instant::now();
+            let blocked_for = bucket.blocked_until.filter(|until| *until > now).map(|until| until - now);
+            let behind_cancels = priority == Priority::Normal && bucket.waiting_cancels > 0;
+            match blocked_for {
+               None if bucket.tokens >= 1.0 && !behind_cancels => {
+                  bucket.tokens -= 1.0;
+                  return;
+               },
+               Some(blocked_for) => blocked_for,
+               None => {
+                  if priority == Priority::Cancel && waiting.is_none() {
+                     bucket.waiting_cancels += 1;
+                     waiting = Some(WaitingCancel(self));
+                  }
+                  let needed = 1.0 + if behind_cancels { bucket.waiting_cancels as f64 } else { 0.0 } - bucket.tokens;
+                  Duration::from_secs_f64(needed.max(0.0) / self.per_second).max(MIN_WAIT)
+               }
+            }
+         };
+         delay_for(wait).await;
+      }
+   }
+
+   /// Brings the bucket in line with what Alpaca says is remaining
+   pub(crate) fn sync(&self, status: &RateLimitStatus) {
+      let mut bucket = self.bucket.lock().unwrap();
+      bucket.tokens = bucket.tokens.min(f64::from(status.remaining));
+      if status.remaining == 0 {
+         let until_reset = (status.reset - Utc::now()).to_std().unwrap_or_default();
+         bucket.blocked_until = Some(Instant::now() + until_reset);
+      }
+   }
+
+   /// Adds the tokens earned since the bucket was last updated
+   fn refill(&self, bucket: &mut Bucket) {
+      let now = Instant::now();
+      let earned = (now - bucket.updated).as_secs_f64() * self.per_second;
+      bucket.tokens = (bucket.tokens + earned).min(self.capacity);
+      bucket.updated = now;
+   }
+}
+
+/// Marks a cancel as waiting for as long as it is alive - so that it is unmarked even if the call is dropped
+struct WaitingCancel<'a>(&'a Limiter);
+impl Drop for WaitingCancel<'_> {
+   fn drop(&mut self) {
+      self.0.bucket.lock().unwrap().waiting_cancels -= 1;
+   }
+}
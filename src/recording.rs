@@ -0,0 +1,187 @@
+use chrono::{ DateTime, Utc };
+use futures::channel::mpsc::{ self, UnboundedReceiver, UnboundedSender };
+use futures::executor::block_on_stream;
+use futures::future;
+use futures::stream::{ self, BoxStream, StreamExt };
+use serde::{ Deserialize, Serialize };
+use snafu::ResultExt;
+use std::fs::File;
+use std::io::{ self, BufRead, BufReader, LineWriter, Write };
+use std::sync::{ Arc, Mutex };
+use tokio::task::JoinHandle;
+use tokio::time::delay_for;
+
+use crate::{ error, streaming, Result, StreamMessage };
+
+/// A frame from the stream, as it is kept in a recording - one per line
+#[derive(Debug, Deserialize, Serialize)]
+struct RecordedFrame {
+   /// When the frame came in
+   at: DateTime<Utc>,
+
+   /// The frame as Alpaca sent it
+   frame: String
+}
+
+/// Records the frames coming in on a stream, so they can be replayed later.
+///
+/// Each frame is written to the file as a line of JSON, with the time it came in.  The writing is done off
+/// the runtime so a slow disk doesn't hold up the stream, and the recording is complete once the streamer
+/// has stopped.  If writing fails, the error is passed on in the stream of events and recording stops.
+///
+/// # Example
+///
+/// To record a day of paper trading:
+///
+/// ``` no run
+/// let alpaca = Alpaca::paper("KEY_ID", "SECRET").await.unwrap();
+///
+/// let recorder = Recorder::create("paper-session.jsonl").unwrap();
+/// let events = Streamer::new(&alpaca).record(recorder).start().await.unwrap();
+/// ```
+pub struct Recorder {
+   path: String,
+   frames: UnboundedSender<RecordedFrame>,
+   writer: Mutex<Writer>,
+   failure: Arc<Mutex<Option<io::Error>>>
+}
+impl Recorder {
+   /// Creates a recorder writing to the file - replacing it if it already exists
+   pub fn create(path: &str) -> Result<Recorder> {
+      let file = File::create(path).context(error::Recording { path })?;
+      let (frames, pending) = mpsc::unbounded();
+
+      Ok(Recorder {
+         path: path.to_string(),
+         frames,
+         writer: Mutex::new(Writer::Pending(file, pending)),
+         failure: Arc::new(Mutex::new(None))
+      })
+   }
+
+   /// Queues a frame to be written to the recording - starting the writer with the first one
+   pub(crate) fn record(&self, frame: &[u8]) -> Result<()> {
+      self.check()?;
+
+      let mut writer = self.writer.lock().unwrap();
+      if let Writer::Pending(..) = *writer {
+         if let Writer::Pending(file, pending) = std::mem::replace(&mut *writer, Writer::Finished) {
+            let failure = self.failure.clone();
+            *writer = Writer::Running(tokio::task::spawn_blocking(move || write_frames(file, pending, failure)));
+         }
+      }
+
+      let _ = self.frames.unbounded_send(RecordedFrame { at: Utc::now(), frame: String::from_utf8_lossy(frame).to_string() });
+      Ok(())
+   }
+
+   /// Waits for the queued frames to be written, once the stream is done with the recorder
+   pub(crate) async fn finish(&self) -> Result<()> {
+      self.frames.close_channel();
+
+      let writer = std::mem::replace(&mut *self.writer.lock().unwrap(), Writer::Finished);
+      if let Writer::Running(task) = writer { let _ = task.await; }
+      self.check()
+   }
+
+   /// Passes on the error the writer hit, if it has failed since the last check
+   fn check(&self) -> Result<()> {
+      match self.failure.lock().unwrap().take() {
+         Some(source) => Err(source).context(error::Recording { path: self.path.as_str() })?,
+         None => Ok(())
+      }
+   }
+}
+
+/// Where the writer of a recording is at
+enum Writer {
+   /// Waiting for the first frame, with the file and the frames to write to it
+   Pending(File, UnboundedReceiver<RecordedFrame>),
+
+   /// Writing frames as they are queued
+   Running(JoinHandle<()>),
+
+   /// Done with - or never needed
+   Finished
+}
+
+/// Writes the frames to the file as they are queued, until the recorder is finished or writing fails
+fn write_frames(file: File, frames: UnboundedReceiver<RecordedFrame>, failure: Arc<Mutex<Option<io::Error>>>) {
+   let mut writer = LineWriter::new(file);
+   for frame in block_on_stream(frames) {
+      let written = serde_json::to_writer(&mut writer, &frame).map_err(io::Error::from).and_then(|_| writeln!(writer));
+      if let Err(e) = written {
+         *failure.lock().unwrap() = Some(e);
+         return;
+      }
+   }
+}
+
+/// How fast a recording is replayed
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ReplaySpeed {
+   /// With the same gaps between events as when they were recorded
+   Original,
+
+   /// As fast as the events are taken
+   Fastest
+}
+
+/// Replays a recording made by a Recorder - giving the same stream of events the streamer gave.
+///
+/// # Example
+///
+/// To run a strategy against a recorded session in real time:
+///
+/// ``` no run
+/// let mut events = Replayer::open("paper-session.jsonl").unwrap().speed(ReplaySpeed::Original).start();
+///
+/// while let Some(event) = events.next().await {
+///    strategy.handle(event.unwrap());
+/// }
+/// ```
+pub struct Replayer {
+   frames: Vec<RecordedFrame>,
+   speed: ReplaySpeed
+}
+impl Replayer {
+   /// Reads a recording to replay it as fast as possible.  Fails if the file can't be read or a line in it
+   /// isn't a recorded frame.
+   pub fn open(path: &str) -> Result<Replayer> {
+      let file = File::open(path).context(error::Recording { path })?;
+
+      let mut frames = Vec::new();
+      for (index, line) in BufReader::new(file).lines().enumerate() {
+         let line = line.context(error::Recording { path })?;
+         if line.trim().is_empty() { continue; }
+         frames.push(serde_json::from_str::<RecordedFrame>(&line).context(error::BadRecording { path, line: index + 1 })?);
+      }
+
+      Ok(Replayer { frames, speed: ReplaySpeed::Fastest })
+   }
+
+   /// Sets how fast to replay the recording
+   pub fn speed(mut self, speed: ReplaySpeed) -> Replayer {
+      self.speed = speed;
+      self
+   }
+
+   /// Starts replaying the events.  The stream ends with the recording.
+   pub fn start(self) -> BoxStream<'static, Result<StreamMessage>> {
+      let original_speed = self.speed == ReplaySpeed::Original;
+      let mut previous = self.frames.first().map(|first| first.at).unwrap_or_else(Utc::now);
+      let steps = self.frames.into_iter().map(|recorded| {
+         let gap = (recorded.at - previous).to_std().unwrap_or_default();
+         previous = recorded.at;
+         (gap, recorded.frame)
+      }).collect::<Vec<_>>();
+
+      stream::iter(steps)
+         .then(move |(gap, frame)| async move {
+            if original_speed { delay_for(gap).await; }
+            streaming::parse(frame.as_bytes())
+         })
+         .filter(|msg| future::ready(streaming::is_event(msg)))
+         .boxed()
+   }
+}
@@ -0,0 +1,111 @@
+use rand::Rng;
+use reqwest::{ header, Method, Request, Response, StatusCode };
+use std::time::Duration;
+
+const DEFAULT_MAX_RETRIES: u32 = 3;
+const DEFAULT_BASE_DELAY: Duration = Duration::from_millis(200);
+const DEFAULT_MAX_DELAY: Duration = Duration::from_secs(10);
+
+/// How calls are retried when Alpaca has a temporary problem.
+///
+/// Calls are retried when they cannot connect or time out, when Alpaca is rate limiting (429) and when
+/// Alpaca has a server error (5xx).  Only calls that are safe to repeat are retried - GETs and DELETEs,
+/// and POSTs that carry a client order ID so that Alpaca rejects a repeated order.
+///
+/// Between tries the delay doubles from the base delay up to the maximum delay, with a random jitter so
+/// that many clients don't retry in step.  If Alpaca says when to retry (the Retry-After header), that
+/// is used instead - though never longer than the maximum delay.
+///
+/// # Example
+///
+/// To retry up to 5 times, starting with a 1 second delay:
+///
+/// ``` no run
+/// let alpaca = AlpacaBuilder::paper("KEY_ID", "SECRET")
+///    .retry_policy(RetryPolicy::new(5).base_delay(Duration::from_secs(1)))
+///    .build().await.unwrap();
+/// ```
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+   max_retries: u32,
+   base_delay: Duration,
+   max_delay: Duration
+}
+impl RetryPolicy {
+   /// Creates a policy that retries a call up to `max_retries` times
+   pub fn new(max_retries: u32) -> RetryPolicy {
+      RetryPolicy { max_retries, ..Default::default() }
+   }
+
+   /// Creates a policy that never retries
+   pub fn none() -> RetryPolicy { RetryPolicy::new(0) }
+
+   /// Sets the delay before the first retry - defaults to 200ms
+   pub fn base_delay(mut self, base_delay: Duration) -> RetryPolicy {
+      self.base_delay = base_delay;
+      self
+   }
+
+   /// Sets the longest delay between retries - defaults to 10 seconds
+   pub fn max_delay(mut self, max_delay: Duration) -> RetryPolicy {
+      self.max_delay = max_delay;
+      self
+   }
+
+   /// The most retries allowed
+   pub(crate) fn max_retries(&self) -> u32 { self.max_retries }
+
+   /// The number of retries allowed for the request - zero if it isn't safe to repeat
+   pub(crate) fn retries_for(&self, request: &Request) -> u32 {
+      let is_idempotent = match *request.method() {
+         Method::GET | Method::DELETE => true,
+         Method::POST => has_client_order_id(request),
+         _ => false
+      };
+      if is_idempotent { self.max_retries } else { 0 }
+   }
+
+   /// Works out how long to wait before the next try, given how many retries have already been made
+   pub(crate) fn delay(&self, retries: u32, response: Option<&Response>) -> Duration {
+      if let Some(retry_after) = response.and_then(retry_after) { return retry_after.min(self.max_delay) }
+
+      let backoff = self.base_delay
+         .checked_mul(2u32.saturating_pow(retries))
+         .unwrap_or(self.max_delay)
+         .min(self.max_delay);
+
+      // wait at least half of the backoff, and a random amount of the other half
+      let half = backoff / 2;
+      half + Duration::from_millis(rand::thread_rng().gen_range(0, half.as_millis() as u64 + 1))
+   }
+}
+impl Default for RetryPolicy {
+   fn default() -> Self {
+      RetryPolicy {
+         max_retries: DEFAULT_MAX_RETRIES,
+         base_delay: DEFAULT_BASE_DELAY,
+         max_delay: DEFAULT_MAX_DELAY
+      }
+   }
+}
+
+/// True if the response is worth retrying
+pub(crate) fn is_retryable(status: StatusCode) -> bool {
+   status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+/// True if the request body is an order with a client order ID
+fn has_client_order_id(request: &Request) -> bool {
+   request.body()
+      .and_then(|body| body.as_bytes())
+      .and_then(|body| serde_json::from_slice::<serde_json::Value>(body).ok())
+      .is_some_and(|body| body.get("client_order_id").is_some_and(|id| !id.is_null()))
+}
+
+/// The delay Alpaca asked for in the Retry-After header, in seconds
+fn retry_after(response: &Response) -> Option<Duration> {
+   response.headers().get(header::RETRY_AFTER)?
+      .to_str().ok()?
+      .trim().parse::<u64>().ok()
+      .map(Duration::from_secs)
+}
@@ -1,12 +1,37 @@
 use chrono::{ DateTime, Utc };
-use futures::{ future, Stream };
+use futures::channel::mpsc::{ self, UnboundedReceiver, UnboundedSender };
+use futures::future::{ self, BoxFuture, FutureExt, Shared };
+use futures::{ Future, Stream };
+use std::pin::Pin;
+use std::task::{ Context, Poll };
 use futures_util::{SinkExt, StreamExt };
 use serde::{ Deserialize, Serialize };
-use std::sync::{ mpsc, Arc, Mutex };
-use tokio_tungstenite::connect_async;
+use snafu::ResultExt;
+use std::sync::{ Arc, Mutex };
+use std::time::{ Duration, Instant };
+use tokio::net::TcpStream;
+use tokio::time::{ delay_for, interval_at, timeout, Interval };
+use tokio_tungstenite::{ connect_async, MaybeTlsStream, WebSocketStream };
 use tokio_tungstenite::tungstenite::protocol::Message;
 
-use crate::{ util, AccountStatus, Alpaca, Order };
+use crate::{ error, util, AccountStatus, Alpaca, ErrorKind, Order, Recorder, Result, RetryPolicy };
+
+const DEFAULT_RECONNECT_BASE_DELAY: Duration = Duration::from_millis(500);
+const DEFAULT_RECONNECT_MAX_DELAY: Duration = Duration::from_secs(30);
+const DEFAULT_PING_INTERVAL: Duration = Duration::from_secs(15);
+const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(45);
+
+/// How long connecting to the stream can take by default - from opening the socket to Alpaca answering the
+/// authenticate and listen messages
+const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long Alpaca has to answer the close message when stopping
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// The streams Alpaca sends messages on that we know how to read
+const KNOWN_STREAMS: &[&str] = &["account_updates", "authorization", "listening", "trade_updates"];
 
 #[derive(Debug, Deserialize, PartialEq)]
 pub enum AuthorizationStatus {
@@ -22,8 +47,8 @@ pub enum AuthorizationAction {
 
 #[derive(Debug, Deserialize)]
 pub struct Authorization {
-   status: AuthorizationStatus,
-   action: AuthorizationAction
+   pub status: AuthorizationStatus,
+   pub action: AuthorizationAction
 }
 
 /// An update that has occurred due to an account change.
@@ -74,7 +99,7 @@ pub enum OrderEvent {
    Fill {
       timestamp: DateTime<Utc>,
       #[serde(deserialize_with = "util::to_f64")] price: f64,
-      #[serde(deserialize_with = "util::to_u32", rename(deserialize="position_qty"))] qty: u32,
+      #[serde(deserialize_with = "util::to_f64", rename(deserialize="position_qty"))] qty: f64,
       order: Order
    },
 
@@ -91,7 +116,7 @@ pub enum OrderEvent {
    PartialFill {
       timestamp: DateTime<Utc>,
       #[serde(deserialize_with = "util::to_f64")] price: f64,
-      #[serde(deserialize_with = "util::to_u32", rename(deserialize="position_qty"))] qty: u32,
+      #[serde(deserialize_with = "util::to_f64", rename(deserialize="position_qty"))] qty: f64,
       order: Order
    },
 
@@ -117,10 +142,67 @@ pub enum OrderEvent {
    /// Sent when the order has been suspended and is not eligible for trading.
    Suspended { order: Order },
 }
+impl OrderEvent {
+   /// The order the event is for
+   pub fn order(&self) -> &Order {
+      match self {
+         OrderEvent::Calculated { order } | OrderEvent::Canceled { order, .. } | OrderEvent::DoneForDay { order } |
+         OrderEvent::Expired { order, .. } | OrderEvent::Fill { order, .. } | OrderEvent::New { order } |
+         OrderEvent::OrderCancelRejected { order } | OrderEvent::OrderReplaceRejected { order } |
+         OrderEvent::PartialFill { order, .. } | OrderEvent::PendingCancel { order } | OrderEvent::PendingNew { order } |
+         OrderEvent::PendingReplace { order } | OrderEvent::Rejected { order, .. } | OrderEvent::Replaced { order, .. } |
+         OrderEvent::Stopped { order } | OrderEvent::Suspended { order } => order
+      }
+   }
+
+   /// The type of the event - without its data
+   pub fn event_type(&self) -> OrderEventType {
+      match self {
+         OrderEvent::Calculated { .. } => OrderEventType::Calculated,
+         OrderEvent::Canceled { .. } => OrderEventType::Canceled,
+         OrderEvent::DoneForDay { .. } => OrderEventType::DoneForDay,
+         OrderEvent::Expired { .. } => OrderEventType::Expired,
+         OrderEvent::Fill { .. } => OrderEventType::Fill,
+         OrderEvent::New { .. } => OrderEventType::New,
+         OrderEvent::OrderCancelRejected { .. } => OrderEventType::OrderCancelRejected,
+         OrderEvent::OrderReplaceRejected { .. } => OrderEventType::OrderReplaceRejected,
+         OrderEvent::PartialFill { .. } => OrderEventType::PartialFill,
+         OrderEvent::PendingCancel { .. } => OrderEventType::PendingCancel,
+         OrderEvent::PendingNew { .. } => OrderEventType::PendingNew,
+         OrderEvent::PendingReplace { .. } => OrderEventType::PendingReplace,
+         OrderEvent::Rejected { .. } => OrderEventType::Rejected,
+         OrderEvent::Replaced { .. } => OrderEventType::Replaced,
+         OrderEvent::Stopped { .. } => OrderEventType::Stopped,
+         OrderEvent::Suspended { .. } => OrderEventType::Suspended
+      }
+   }
+}
+
+/// The types of order event - see OrderEvent for what each one means
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum OrderEventType {
+   Calculated,
+   Canceled,
+   DoneForDay,
+   Expired,
+   Fill,
+   New,
+   OrderCancelRejected,
+   OrderReplaceRejected,
+   PartialFill,
+   PendingCancel,
+   PendingNew,
+   PendingReplace,
+   Rejected,
+   Replaced,
+   Stopped,
+   Suspended
+}
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct ListenStream {
-   streams: Vec<String>
+   pub streams: Vec<String>
 }
 
 /// The possible actions we can push on to streams
@@ -131,6 +213,7 @@ enum ActionMessage {
 }
 
 /// The possible event streams that we can listen on
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug, Deserialize)]
 #[serde(content = "data", tag = "stream")]
 pub enum StreamMessage {
@@ -140,19 +223,105 @@ pub enum StreamMessage {
 
    #[serde(rename = "authorization")] Authorization(Authorization),
 
+   /// Sent when Alpaca is listening on a new set of streams, after subscribing or unsubscribing through a
+   /// StreamHandle
    #[serde(rename = "listening")] Listening(ListenStream),
 
    /// This stream provides clients with updates pertaining to orders placed at Alpaca.  This includes
    /// order fills, partial fills, as well as cancellations and rejections of orders
    #[serde(rename = "trade_updates")] Order(OrderEvent),
+
+   /// Sent after the connection dropped and the streamer reconnected.  Events may have been missed while
+   /// it was down, so anything being tracked should be checked against the REST API.
+   #[serde(skip_deserializing)] Reconnected,
+
+   /// A message that isn't understood - such as a new stream or event type - with its raw JSON
+   #[serde(skip_deserializing)] Unknown(serde_json::Value),
 }
 
+/// The streams of events that can be listened on
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StreamKind {
+   /// Updates to the account - see StreamMessage::Account
+   Account,
 
+   /// Updates to orders - see StreamMessage::Order
+   Order
+}
+impl StreamKind {
+   /// The name Alpaca has for the stream
+   fn name(self) -> &'static str {
+      match self {
+         StreamKind::Account => "account_updates",
+         StreamKind::Order => "trade_updates"
+      }
+   }
+}
+
+/// Changes sent from a handle to the task running the connection
+#[derive(Debug)]
+enum Command {
+   /// Listen on the current set of streams
+   Listen,
+
+   /// Close the connection and finish
+   Stop
+}
+
+/// Why passing on the events from a connection ended
+enum Relayed {
+   /// The connection dropped - so reconnect
+   Dropped,
+
+   /// The streamer was stopped or nobody is listening any more - so finish
+   Finished
+}
+
+/// Where a streamer is at
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StreamState {
+   /// The streamer hasn't been started
+   NotStarted,
+
+   /// Connected and passing on events
+   Connected,
+
+   /// The connection dropped and the streamer is reconnecting
+   Reconnecting,
+
+   /// The streamer has stopped - either it was asked to, nobody is listening any more or it could not
+   /// reconnect
+   Stopped
+}
+
+/// What a streamer, its handles and the task running the connection share
+struct Control {
+   streams: Mutex<Vec<StreamKind>>,
+   state: Mutex<StreamState>,
+   task: Mutex<Option<Shared<BoxFuture<'static, ()>>>>
+}
+impl Control {
+   fn set_state(&self, state: StreamState) { *self.state.lock().unwrap() = state; }
+}
 
 /// Realtime event streamer
 ///
-/// Currently streams updates to orders and the account.  To use the streamer, first create a new one
-/// and then listen on the stream of events coming in.
+/// Streams updates to orders and the account - or just the streams picked.  To use the streamer, first
+/// create a new one and then start it to get a Subscription - the stream of events coming in.
+///
+/// The streamer keeps its own copy of the Alpaca context, so it can be moved into a spawned task or kept
+/// in a long lived struct.  StreamHandles, from either the streamer or the subscription, can be cloned
+/// and used elsewhere to change the streams, check on the state or stop it.
+///
+/// If the connection drops, the streamer reconnects - authenticating and listening again - and sends a
+/// `StreamMessage::Reconnected` so that any events missed in between can be caught up on.
+///
+/// Starting the stream waits until Alpaca has accepted the credentials and is listening on the streams,
+/// failing with `InvalidCredentials` if the credentials are refused and `StreamSetup` for any other answer.
+///
+/// Problems come through the stream as errors rather than ending it - a frame that isn't JSON is an
+/// `InternalJSON` error and the stream carries on.  If the streamer can't reconnect, the last connection
+/// error comes through as `StreamingFailed` and the stream ends.
 ///
 /// # Example
 ///
@@ -162,81 +331,480 @@ pub enum StreamMessage {
 /// let alpaca = Alpaca::live("KEY_ID", "SECRET").await.unwrap();
 ///
 /// let streamer = Streamer:new(&alpaca);
-/// streamer.start().await
+/// streamer.start().await.unwrap()
 ///    .for_each(|msg| {
 ///       match msg {
-///          StreamMessage::Account(_) => println!("Got an account update!"),
-///          StreamMessage::Order(_) => println!("Got an order update!"),
-///          _ => println!("Got an unexpected msg")
+///          Ok(StreamMessage::Account(_)) => println!("Got an account update!"),
+///          Ok(StreamMessage::Order(_)) => println!("Got an order update!"),
+///          Ok(StreamMessage::Reconnected) => println!("Missed some updates - time to check the open orders"),
+///          Ok(_) => println!("Got an unexpected msg"),
+///          Err(e) => println!("Something went wrong - {}", e)
 ///       }
 ///       future::ready(())
 ///    })
 ///    .await;
 /// ```
-pub struct Streamer<'a> {
-   alpaca: &'a Alpaca,
-   shutdown: Arc<Mutex<bool>>
-}
-impl<'a> Streamer<'a> {
-   /// Creates a new event streamer.
-   pub fn new(alpaca: &'a Alpaca) -> Streamer<'a> { Streamer { alpaca, shutdown: Arc::new(Mutex::new(false)) } }
-
-   /// Starts the stream of events
-   pub async fn start(&self) -> impl Stream<Item = StreamMessage> {
-      let (host, auth_block) = self.alpaca.stream();
-      let (tx, rx) = mpsc::channel();
-
-      let (stream, _) = connect_async(host).await.unwrap();
-      let (mut sink, source) = stream.split();
-
-      // First - authenticate & set up the stream we want to listen on
-      //         right now listen on all streams.  TODO - make it configurable
-      let listen_msg = ActionMessage::Listen(ListenStream { streams: vec!["trade_updates".to_string(), "account_updates".to_string()] });
-      let msg = serde_json::to_string(&listen_msg).unwrap();
-      tx.send(Message::Text(auth_block)).unwrap();
-      tx.send(Message::Text(msg)).unwrap();
-
-      // spawn a separate thread for sending out messages
-      let shutdown = self.shutdown.clone();
-      tokio::spawn(async move {
-         loop {
-            // stop on shutdown notification
-            if *(shutdown.lock().unwrap()) { break; }
-
-            // we're still running - so get a message and send it out.
-            // TODO - change this to WAIT on receive so that we don't block shutdown
-            let msg = rx.recv().unwrap();
-            sink.send(msg).await.unwrap();
+pub struct Streamer {
+   alpaca: Alpaca,
+   reconnect_policy: RetryPolicy,
+   keepalive: Keepalive,
+   connect_timeout: Duration,
+   recorder: Option<Recorder>,
+   handle: StreamHandle,
+   commands: UnboundedReceiver<Command>
+}
+impl Streamer {
+   /// Creates a new event streamer, listening on both the order and account streams.
+   pub fn new(alpaca: &Alpaca) -> Streamer {
+      let reconnect_policy = RetryPolicy::new(u32::MAX)
+         .base_delay(DEFAULT_RECONNECT_BASE_DELAY)
+         .max_delay(DEFAULT_RECONNECT_MAX_DELAY);
+      let (command_tx, commands) = mpsc::unbounded();
+      let control = Control {
+         streams: Mutex::new(vec![StreamKind::Order, StreamKind::Account]),
+         state: Mutex::new(StreamState::NotStarted),
+         task: Mutex::new(None)
+      };
+
+      let keepalive = Keepalive { ping_interval: Some(DEFAULT_PING_INTERVAL), idle_timeout: Some(DEFAULT_IDLE_TIMEOUT) };
+
+      Streamer { alpaca: alpaca.clone(), reconnect_policy, keepalive, connect_timeout: DEFAULT_CONNECT_TIMEOUT, recorder: None, handle: StreamHandle { control: Arc::new(control), commands: command_tx }, commands }
+   }
+
+   /// Sets the streams to listen on
+   ///
+   /// # Example
+   ///
+   /// To only get updates to orders:
+   ///
+   /// ``` no run
+   /// let streamer = Streamer::new(&alpaca).streams(&[StreamKind::Order]);
+   /// ```
+   pub fn streams(self, streams: &[StreamKind]) -> Streamer {
+      *self.handle.control.streams.lock().unwrap() = streams.to_vec();
+      self
+   }
+
+   /// Sets how the streamer reconnects when the connection drops - by default it keeps trying, waiting up
+   /// to 30 seconds between tries.  The stream of events ends once the retries run out.
+   pub fn reconnect_policy(mut self, reconnect_policy: RetryPolicy) -> Streamer {
+      self.reconnect_policy = reconnect_policy;
+      self
+   }
+
+   /// Sets how often the streamer pings Alpaca to keep the connection alive - by default every 15 seconds.
+   /// `None` stops the pings.
+   pub fn ping_interval(mut self, ping_interval: Option<Duration>) -> Streamer {
+      self.keepalive.ping_interval = ping_interval;
+      self
+   }
+
+   /// Sets how long the connection can go without hearing anything from Alpaca before it is treated as
+   /// dead and reconnected - by default 45 seconds.  `None` trusts the connection however quiet it gets.
+   ///
+   /// # Example
+   ///
+   /// To notice a half-open connection within a few seconds:
+   ///
+   /// ``` no run
+   /// let streamer = Streamer::new(&alpaca)
+   ///    .ping_interval(Some(Duration::from_secs(1)))
+   ///    .idle_timeout(Some(Duration::from_secs(3)));
+   /// ```
+   pub fn idle_timeout(mut self, idle_timeout: Option<Duration>) -> Streamer {
+      self.keepalive.idle_timeout = idle_timeout;
+      self
+   }
+
+   /// Sets how long connecting - or reconnecting - to the stream can take, including Alpaca answering the
+   /// authenticate and listen messages - by default 10 seconds
+   pub fn connect_timeout(mut self, connect_timeout: Duration) -> Streamer {
+      self.connect_timeout = connect_timeout;
+      self
+   }
+
+   /// Records the frames that come in on the stream - to replay them later with a Replayer
+   pub fn record(mut self, recorder: Recorder) -> Streamer {
+      self.recorder = Some(recorder);
+      self
+   }
+
+   /// Gets a handle for the streamer - to change its streams, check on it or stop it once it is running
+   pub fn handle(&self) -> StreamHandle { self.handle.clone() }
+
+   /// Starts the stream of events.  Fails if the first connection to the stream can't be made, the
+   /// credentials are refused or Alpaca won't listen on the streams.
+   pub async fn start(self) -> Result<Subscription> {
+      let (host, auth_block) = self.alpaca.stream()?;
+      let control = self.handle.control.clone();
+      let session = Session {
+         host,
+         auth_block,
+         keepalive: self.keepalive,
+         connect_timeout: self.connect_timeout,
+         recorder: self.recorder,
+         control: control.clone()
+      };
+
+      let socket = session.connect().await?;
+      let (tx, events) = mpsc::unbounded();
+      control.set_state(StreamState::Connected);
+
+      let task = tokio::spawn(supervise(socket, session, self.commands, self.reconnect_policy, tx));
+      *control.task.lock().unwrap() = Some(task.map(|_| ()).boxed().shared());
+
+      Ok(Subscription { events, handle: self.handle })
+   }
+}
+
+/// The stream of events from a running streamer.
+///
+/// The subscription owns the streamer - dropping it stops the streamer.  Use `handle` to get handles that
+/// can be passed around to change the streams, check on it or stop it.
+pub struct Subscription {
+   events: UnboundedReceiver<Result<StreamMessage>>,
+   handle: StreamHandle
+}
+impl Subscription {
+   /// Gets a handle for the streamer
+   pub fn handle(&self) -> StreamHandle { self.handle.clone() }
+
+   /// Where the streamer is at
+   pub fn state(&self) -> StreamState { self.handle.state() }
+
+   /// Stops the streamer - see StreamHandle.stop
+   pub async fn stop(&self) { self.handle.stop().await }
+}
+impl Stream for Subscription {
+   type Item = Result<StreamMessage>;
+
+   fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+      self.events.poll_next_unpin(cx)
+   }
+}
+impl Drop for Subscription {
+   fn drop(&mut self) { self.handle.request_stop(); }
+}
+
+/// A handle on a streamer - to change the streams it listens on, check on it or stop it.
+///
+/// Handles are cheap to clone and can be moved into other tasks.  Changes made before the streamer is
+/// started are picked up when it starts, and changes are kept when the streamer reconnects.
+///
+/// # Example
+///
+/// To start getting account updates as well:
+///
+/// ``` no run
+/// let streamer = Streamer::new(&alpaca).streams(&[StreamKind::Order]);
+/// let handle = streamer.handle();
+/// let events = streamer.start().await.unwrap();
+///
+/// handle.subscribe(StreamKind::Account).unwrap();
+/// ```
+#[derive(Clone)]
+pub struct StreamHandle {
+   control: Arc<Control>,
+   commands: UnboundedSender<Command>
+}
+impl StreamHandle {
+   /// Starts listening on a stream as well as the current ones.  Fails if the streamer has stopped.
+   pub fn subscribe(&self, stream: StreamKind) -> Result<()> {
+      {
+         let mut streams = self.control.streams.lock().unwrap();
+         if !streams.contains(&stream) { streams.push(stream); }
+      }
+      self.relisten()
+   }
+
+   /// Stops listening on a stream.  Fails if the streamer has stopped.
+   pub fn unsubscribe(&self, stream: StreamKind) -> Result<()> {
+      self.control.streams.lock().unwrap().retain(|current| *current != stream);
+      self.relisten()
+   }
+
+   /// The streams being listened on
+   pub fn streams(&self) -> Vec<StreamKind> { self.control.streams.lock().unwrap().clone() }
+
+   /// Where the streamer is at
+   pub fn state(&self) -> StreamState { *self.control.state.lock().unwrap() }
+
+   /// Stops the streamer.  The connection is closed cleanly, and this returns once it has been torn down
+   /// and the stream of events has ended.
+   ///
+   /// # Example
+   ///
+   /// To stop streaming when the service is shut down:
+   ///
+   /// ``` no run
+   /// signal::ctrl_c().await.unwrap();
+   /// handle.stop().await;
+   /// ```
+   pub async fn stop(&self) {
+      self.request_stop();
+
+      let task = self.control.task.lock().unwrap().clone();
+      if let Some(task) = task { task.await; }
+   }
+
+   /// Tells the streamer to stop, without waiting for it to finish
+   pub(crate) fn request_stop(&self) {
+      let _ = self.commands.unbounded_send(Command::Stop);
+   }
+
+   /// Has the streamer listen on the current set of streams
+   fn relisten(&self) -> Result<()> {
+      match self.commands.unbounded_send(Command::Listen) {
+         Ok(_) => Ok(()),
+         Err(_) => error::StreamStopped.fail()?
+      }
+   }
+}
+
+/// How the streamer makes sure the connection is still alive
+#[derive(Clone, Copy)]
+struct Keepalive {
+   ping_interval: Option<Duration>,
+   idle_timeout: Option<Duration>
+}
+
+/// What is needed to (re)connect to the stream
+struct Session {
+   host: String,
+   auth_block: String,
+   keepalive: Keepalive,
+   connect_timeout: Duration,
+   recorder: Option<Recorder>,
+   control: Arc<Control>
+}
+impl Session {
+   /// Connects to the stream - authenticating and setting up the streams we want to listen on
+   async fn connect(&self) -> Result<Socket> {
+      let connecting = async {
+         let (mut socket, _) = connect_async(self.host.as_str()).await.context(error::StreamingFailed)?;
+         self.set_up(&mut socket).await?;
+         Ok(socket)
+      };
+
+      match timeout(self.connect_timeout, connecting).await {
+         Ok(result) => result,
+         Err(_) => error::StreamSetup { reason: "Alpaca did not answer in time" }.fail()?
+      }
+   }
+
+   /// Authenticates and listens on the streams, waiting for Alpaca to accept each of them
+   async fn set_up(&self, socket: &mut Socket) -> Result<()> {
+      socket.send(Message::Text(self.auth_block.clone())).await.context(error::StreamingFailed)?;
+      match reply(socket).await? {
+         StreamMessage::Authorization(Authorization { status: AuthorizationStatus::Authorized, action: AuthorizationAction::Authenticate }) => {},
+         StreamMessage::Authorization(Authorization { status: AuthorizationStatus::Unauthorized, .. }) => error::InvalidCredentials { status: None }.fail()?,
+         _ => error::StreamSetup { reason: "Alpaca did not answer the authentication" }.fail()?
+      }
+
+      let streams = self.streams();
+      socket.send(listen_message(&streams)?).await.context(error::StreamingFailed)?;
+      match reply(socket).await? {
+         StreamMessage::Listening(listening) if streams.iter().all(|stream| listening.streams.contains(stream)) => Ok(()),
+         _ => error::StreamSetup { reason: format!("Alpaca is not listening on all of {:?}", streams) }.fail()?
+      }
+   }
+
+   /// The names of the streams to listen on
+   fn streams(&self) -> Vec<String> {
+      self.control.streams.lock().unwrap().iter().map(|stream| stream.name().to_string()).collect()
+   }
+}
+
+/// Builds the message to listen on the streams
+fn listen_message(streams: &[String]) -> Result<Message> {
+   let listen_msg = ActionMessage::Listen(ListenStream { streams: streams.to_vec() });
+   Ok(Message::Text(serde_json::to_string(&listen_msg).context(error::InternalJSON)?))
+}
+
+/// Waits for Alpaca to answer an authenticate or listen message
+async fn reply(socket: &mut Socket) -> Result<StreamMessage> {
+   // pings are answered with their own data by tungstenite, the next time the socket is read
+   while let Some(msg) = socket.next().await {
+      let frame = match msg.context(error::StreamingFailed)? {
+         Message::Text(value) => value.into_bytes(),
+         Message::Binary(value) => value,
+         _ => continue
+      };
+      let msg = match parse(&frame) {
+         Ok(msg) => msg,
+         Err(e) => error::StreamSetup { reason: format!("Alpaca's answer could not be read - {}", e) }.fail()?
+      };
+      if let StreamMessage::Authorization(_) | StreamMessage::Listening(_) = msg { return Ok(msg) }
+   }
+   error::StreamSetup { reason: "the connection closed" }.fail()?
+}
+
+/// Keeps the connection to the stream up - passing on the events that come in, and reconnecting with backoff
+/// when the connection drops.  Finishes when the streamer is stopped, nobody is listening any more or the
+/// reconnect policy gives up - in which case the last error is passed on.  Any recording is finished off
+/// before the streamer is marked as stopped.
+async fn supervise(
+   socket: Socket, session: Session, commands: UnboundedReceiver<Command>, policy: RetryPolicy, tx: UnboundedSender<Result<StreamMessage>>
+) {
+   keep_connected(socket, &session, commands, policy, &tx).await;
+   if let Some(recorder) = &session.recorder {
+      if let Err(e) = recorder.finish().await { let _ = tx.unbounded_send(Err(e)); }
+   }
+   session.control.set_state(StreamState::Stopped);
+}
+
+/// Passes on events and reconnects until the streamer finishes
+async fn keep_connected(
+   mut socket: Socket, session: &Session, mut commands: UnboundedReceiver<Command>, policy: RetryPolicy,
+   tx: &UnboundedSender<Result<StreamMessage>>
+) {
+   loop {
+      if let Relayed::Finished = relay(&mut socket, session, &mut commands, tx).await { return; }
+      session.control.set_state(StreamState::Reconnecting);
+      drop(socket);
+
+      let mut retries = 0;
+      socket = loop {
+         if tx.is_closed() { return; }
+
+         let connected = match unless_stopped(session.connect(), &mut commands).await {
+            Some(connected) => connected,
+            None => return
+         };
+         match connected {
+            Ok(socket) => break socket,
+            Err(e) if retries < policy.max_retries() && e.kind() != ErrorKind::InvalidCredentials => {
+               if unless_stopped(delay_for(policy.delay(retries, None)), &mut commands).await.is_none() { return; }
+               retries += 1;
+            },
+            Err(e) => {
+               let _ = tx.unbounded_send(Err(e));
+               return;
+            }
          }
-      });
-
-      // Next - set up our stream & remap stuff coming in
-      let pong_tx = tx.clone();
-      let shutdown = self.shutdown.clone();
-      source
-         .filter_map(move |msg| {
-            match msg.unwrap() {
-               Message::Ping(_) => { pong_tx.send(Message::Pong("pong".as_bytes().to_vec())).unwrap(); },
-               Message::Close(_) => { *(shutdown.lock().unwrap()) = true; },
-               Message::Text(value) => { return future::ready(Some(value)); },
-               Message::Binary(value) => { return future::ready(Some(String::from_utf8(value).unwrap())); },
-               _ => {}
-            };
-            return future::ready(None)
-         })
-         .filter_map(|msg| {
-            match serde_json::from_str(&msg).unwrap() {
-               StreamMessage::Order(order) => future::ready(Some(StreamMessage::Order(order))),
-               StreamMessage::Account(account) => future::ready(Some(StreamMessage::Account(account))),
-               _ => future::ready(None)
+      };
+      session.control.set_state(StreamState::Connected);
+      if tx.unbounded_send(Ok(StreamMessage::Reconnected)).is_err() { return; }
+   }
+}
+
+/// Runs a step of reconnecting, giving up on it if the streamer is stopped first
+async fn unless_stopped<T>(step: impl Future<Output = T>, commands: &mut UnboundedReceiver<Command>) -> Option<T> {
+   tokio::pin!(step);
+
+   // changes to the streams are picked up when reconnecting, so only stopping matters here
+   let mut has_handles = true;
+   loop {
+      tokio::select! {
+         result = &mut step => return Some(result),
+         command = commands.next(), if has_handles => {
+            match command {
+               Some(Command::Stop) => return None,
+               Some(Command::Listen) => {},
+               None => has_handles = false
             }
-         })
+         }
+      }
+   }
+}
+
+/// Passes on the events coming in on the connection, and the changes coming in from handles, until the
+/// connection drops or the streamer is stopped.  The connection is pinged to keep it alive, and treated as
+/// dropped if nothing comes in on it for too long.
+async fn relay(
+   socket: &mut Socket, session: &Session, commands: &mut UnboundedReceiver<Command>, tx: &UnboundedSender<Result<StreamMessage>>
+) -> Relayed {
+   let mut has_handles = true;
+   let mut pings = session.keepalive.ping_interval.map(|period| interval_at((Instant::now() + period).into(), period));
+   let mut last_heard = Instant::now();
+   loop {
+      let msg = tokio::select! {
+         msg = socket.next() => msg,
+         _ = next_ping(&mut pings) => {
+            if socket.send(Message::Ping(Vec::new())).await.is_err() { return Relayed::Dropped }
+            continue;
+         },
+         _ = gone_quiet(last_heard, session.keepalive.idle_timeout) => return Relayed::Dropped,
+         command = commands.next(), if has_handles => {
+            match command {
+               Some(Command::Listen) => {
+                  match listen_message(&session.streams()) {
+                     Ok(listen) => if socket.send(listen).await.is_err() { return Relayed::Dropped },
+                     Err(e) => if tx.unbounded_send(Err(e)).is_err() { break }
+                  }
+               },
+               Some(Command::Stop) => break,
+               None => has_handles = false
+            }
+            continue;
+         }
+      };
+
+      // any frame - including the answer to a ping - shows the connection is alive.  Pings from Alpaca are
+      // answered with their own data by tungstenite, the next time the socket is read.
+      last_heard = Instant::now();
+      let frame = match msg {
+         Some(Ok(Message::Text(value))) => value.into_bytes(),
+         Some(Ok(Message::Binary(value))) => value,
+         Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return Relayed::Dropped,
+         _ => continue
+      };
+      if let Some(recorder) = &session.recorder {
+         if let Err(e) = recorder.record(&frame) {
+            if tx.unbounded_send(Err(e)).is_err() { break; }
+         }
+      }
+
+      let msg = parse(&frame);
+      if is_event(&msg) && tx.unbounded_send(msg).is_err() { break; }
+   }
+
+   close(socket).await;
+   Relayed::Finished
+}
+
+/// Waits until it is time to ping the connection - forever if there are no pings
+async fn next_ping(pings: &mut Option<Interval>) {
+   match pings {
+      Some(pings) => { pings.tick().await; },
+      None => future::pending().await
+   }
+}
+
+/// Waits until nothing has come in on the connection for the idle timeout - forever if there isn't one
+async fn gone_quiet(last_heard: Instant, idle_timeout: Option<Duration>) {
+   match idle_timeout {
+      Some(idle_timeout) => delay_for((last_heard + idle_timeout).saturating_duration_since(Instant::now())).await,
+      None => future::pending().await
    }
+}
+
+/// Closes the connection cleanly - sending a Close and giving Alpaca a while to answer it
+async fn close(socket: &mut Socket) {
+   if socket.close(None).await.is_err() { return; }
+   let _ = timeout(CLOSE_TIMEOUT, async { while let Some(Ok(_)) = socket.next().await {} }).await;
+}
+
+/// True if the message is passed on in the stream of events - authorization replies are not
+pub(crate) fn is_event(msg: &Result<StreamMessage>) -> bool {
+   !matches!(msg, Ok(StreamMessage::Authorization(_)))
+}
+
+/// Reads a message from a frame on the stream.  Frames that are JSON but on a stream or of an event type we
+/// don't know about come through as Unknown - known messages that can't be read are an error.
+pub(crate) fn parse(frame: &[u8]) -> Result<StreamMessage> {
+   let value = serde_json::from_slice::<serde_json::Value>(frame).context(error::InternalJSON)?;
+   if !is_known(&value) { return Ok(StreamMessage::Unknown(value)) }
+
+   Ok(serde_json::from_value::<StreamMessage>(value).context(error::InternalJSON)?)
+}
 
-   /// Stops the stream of events
-   pub fn stop(&mut self) {
-      let mut shutdown = self.shutdown.lock().unwrap();
-      *shutdown = true;
+/// True if the message is on a stream - and for order events, of a type - that we know how to read
+fn is_known(value: &serde_json::Value) -> bool {
+   match value["stream"].as_str() {
+      Some("trade_updates") => serde_json::from_value::<OrderEventType>(value["data"]["event"].clone()).is_ok(),
+      Some(stream) => KNOWN_STREAMS.contains(&stream),
+      None => false
    }
 }
 
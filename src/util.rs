@@ -1,3 +1,4 @@
+use chrono::NaiveTime;
 use serde::{ de, Deserialize, Deserializer, Serializer };
 use serde_json::Value;
 use std::fmt::Display;
@@ -5,11 +6,16 @@ use std::fmt::Display;
 pub fn to_f64<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
    Ok(match Value::deserialize(deserializer)? {
        Value::String(s) => s.parse().map_err(de::Error::custom)?,
-       Value::Number(num) => num.as_f64().ok_or(de::Error::custom("Invalid number"))? as f64,
+       Value::Number(num) => num.as_f64().ok_or_else(|| de::Error::custom("Invalid number"))?,
        _ => return Err(de::Error::custom("wrong type"))
    })
 }
 
+pub fn to_naive_time<'de, D: Deserializer<'de>>(deserializer: D) -> Result<NaiveTime, D::Error> {
+   let s = String::deserialize(deserializer)?;
+   NaiveTime::parse_from_str(&s, "%H:%M").map_err(de::Error::custom)
+}
+
 pub fn to_optional_f64<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<f64>, D::Error> {
    #[derive(Deserialize)]
    struct Wrapper(#[serde(deserialize_with = "to_f64")] f64);
@@ -18,14 +24,6 @@ pub fn to_optional_f64<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Opt
    Ok(v.map(|Wrapper(a)| a))   
 }
 
-pub fn to_u32<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u32, D::Error> {
-   Ok(match Value::deserialize(deserializer)? {
-       Value::String(s) => s.parse().map_err(de::Error::custom)?,
-       Value::Number(num) => num.as_u64().ok_or(de::Error::custom("Invalid number"))? as u32,
-       _ => return Err(de::Error::custom("wrong type"))
-   })
-}
-
 pub fn to_string<T: Display, S: Serializer>(value: &T, serializer: S) -> Result<S::Ok, S::Error> {
    serializer.collect_str(value)
 }
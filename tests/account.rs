@@ -1,4 +1,6 @@
-use alpaca_finance::{ Account, AccountStatus };
+#![allow(clippy::bool_assert_comparison)]
+
+use alpaca_finance::{ Account, AccountStatus, Error, ErrorKind, RetryPolicy };
 use mockito::Mock;
 use std::fs::File;
 use std::io::prelude::*;
@@ -74,4 +76,96 @@ fn get_account_bad_data() {
    block_on(Account::get(&alpaca)).unwrap();
 
    // THEN - we get an error
-}
\ No newline at end of file
+}
+
+/// Gets the account from Alpaca when it fails the call with the status and body
+fn get_account_failing(status: usize, body: &str) -> Error {
+   let alpaca = block_on(common::build_alpaca_with(|builder| builder.retry_policy(RetryPolicy::none())));
+   let _m = common::build_mock("GET", "/v2/account")
+      .with_status(status)
+      .with_body(body)
+      .create();
+
+   block_on(Account::get(&alpaca)).unwrap_err()
+}
+
+#[test]
+fn get_account_unauthorized() {
+   //! Ensure that unaccepted credentials are reported as such
+
+   // GIVEN - Alpaca not accepting our credentials
+   // WHEN - we get our account
+   let error = get_account_failing(401, r#"{"message":"unauthorized."}"#);
+
+   // THEN - we get a credentials error with the status
+   assert_eq!(ErrorKind::InvalidCredentials, error.kind());
+   assert_eq!(Some(401), error.http_status());
+}
+
+#[test]
+fn get_account_forbidden() {
+   //! Ensure that a call refused for a reason is not mistaken for bad credentials
+
+   // GIVEN - Alpaca refusing the call with a reason
+   // WHEN - we get our account
+   let error = get_account_failing(403, r#"{"code":40310000,"message":"account is not allowed to trade"}"#);
+
+   // THEN - we get a forbidden error with the reason
+   assert_eq!(ErrorKind::Forbidden, error.kind());
+   assert_eq!(Some(40310000), error.api_code());
+   assert!(error.to_string().contains("account is not allowed to trade"));
+}
+
+#[test]
+fn get_account_not_found() {
+   //! Ensure that a missing resource is reported as not found
+
+   // GIVEN - Alpaca not knowing the account
+   // WHEN - we get our account
+   let error = get_account_failing(404, r#"{"message":"Not Found"}"#);
+
+   // THEN - we get a not found error
+   assert_eq!(ErrorKind::NotFound, error.kind());
+   assert_eq!(Some(404), error.http_status());
+}
+
+#[test]
+fn get_account_invalid() {
+   //! Ensure that Alpaca's reason is kept when a call is rejected as invalid
+
+   // GIVEN - Alpaca rejecting the call
+   // WHEN - we get our account
+   let error = get_account_failing(422, r#"{"code":40010000,"message":"invalid request"}"#);
+
+   // THEN - we get an invalid error with the reason
+   assert_eq!(ErrorKind::Invalid, error.kind());
+   assert_eq!(Some(40010000), error.api_code());
+   assert!(error.to_string().contains("invalid request"));
+}
+
+#[test]
+fn get_account_rate_limited() {
+   //! Ensure that rate limiting is reported as retryable
+
+   // GIVEN - Alpaca rate limiting us
+   // WHEN - we get our account
+   let error = get_account_failing(429, r#"{"message":"too many requests."}"#);
+
+   // THEN - we get a rate limited error that can be retried
+   assert_eq!(ErrorKind::RateLimited, error.kind());
+   assert!(error.is_retryable());
+}
+
+#[test]
+fn get_account_alpaca_down() {
+   //! Ensure that server errors are reported as Alpaca being down
+
+   // GIVEN - Alpaca having a server error
+   // WHEN - we get our account
+   let error = get_account_failing(500, "");
+
+   // THEN - we get an Alpaca down error that can be retried
+   assert_eq!(ErrorKind::AlpacaDown, error.kind());
+   assert_eq!(Some(500), error.http_status());
+   assert!(error.is_retryable());
+}
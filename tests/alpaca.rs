@@ -0,0 +1,336 @@
+use alpaca_finance::{ Account, Alpaca, AlpacaBuilder, Clock, Endpoint, ErrorKind, Order, OrderType, Position, RateLimiter, RetryPolicy, TimeInForce };
+use chrono::{ TimeZone, Utc };
+use std::env;
+use std::fs;
+use std::io::prelude::*;
+use std::net::TcpListener;
+use std::sync::{ Mutex, MutexGuard };
+use std::thread;
+use std::time::{ Duration, Instant };
+use tokio_test::block_on;
+
+mod common;
+
+/// Serialises the tests that change the environment, which every test in the binary shares
+static ENV_LOCK: Mutex<()> = Mutex::new(());
+
+/// Sets environment variables while it is held, putting back what was there before when dropped
+struct EnvGuard {
+   previous: Vec<(&'static str, Option<String>)>,
+   _lock: MutexGuard<'static, ()>
+}
+impl EnvGuard {
+   fn set(vars: &[(&'static str, String)]) -> EnvGuard {
+      let lock = ENV_LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+      let previous = vars.iter().map(|(name, value)| {
+         let previous = env::var(name).ok();
+         env::set_var(name, value);
+         (*name, previous)
+      }).collect();
+
+      EnvGuard { previous, _lock: lock }
+   }
+}
+impl Drop for EnvGuard {
+   fn drop(&mut self) {
+      for (name, value) in &self.previous {
+         match value {
+            Some(value) => env::set_var(name, value),
+            None => env::remove_var(name)
+         }
+      }
+   }
+}
+
+/// Starts a bare bones HTTP server, separate from the mockito one, that answers every request with the body
+fn start_server(body: &'static str) -> String {
+   let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+   let url = format!("http://{}", listener.local_addr().unwrap());
+
+   thread::spawn(move || {
+      for stream in listener.incoming() {
+         let mut stream = stream.unwrap();
+         let mut request = [0; 4096];
+         let _ = stream.read(&mut request).unwrap();
+         let response = format!("HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\n\r\n{}", body.len(), body);
+         stream.write_all(response.as_bytes()).unwrap();
+      }
+   });
+   url
+}
+
+#[test]
+fn build_with_user_agent() {
+   //! Ensure that the client settings are used for calls
+
+   // GIVEN - Alpaca expecting our user agent
+   let m = common::build_mock("GET", "/v2/clock")
+      .match_header("user-agent", "my-bot/1.0")
+      .expect(1)
+      .create();
+
+   // WHEN - we build the context with the user agent
+   let builder = AlpacaBuilder::new("someKey", "someSecret", Endpoint::custom(&mockito::server_url()))
+      .user_agent("my-bot/1.0")
+      .connect_timeout(Duration::from_secs(1))
+      .timeout(Duration::from_secs(5));
+   block_on(builder.build()).unwrap();
+
+   // THEN - the check call was made with it
+   m.assert();
+}
+
+#[test]
+#[should_panic(expected = "ClientConfig")]
+fn build_bad_certificate() {
+   //! Ensure that we fail gracefully when the client settings are invalid
+
+   // GIVEN - a root certificate that isn't PEM
+   let builder = AlpacaBuilder::paper("someKey", "someSecret")
+      .root_certificate(b"not a certificate");
+
+   // WHEN - we build the context
+   block_on(builder.build()).unwrap();
+
+   // THEN - we get an error
+}
+
+#[test]
+fn separate_endpoints() {
+   //! Ensure that two contexts can talk to two different servers at the same time
+
+   // GIVEN - two servers with different clocks
+   let open = start_server(r#"{"timestamp":"2018-04-01T12:00:00Z","is_open":true,"next_open":"2018-04-02T13:30:00Z","next_close":"2018-04-01T20:00:00Z"}"#);
+   let closed = start_server(r#"{"timestamp":"2018-04-01T22:00:00Z","is_open":false,"next_open":"2018-04-02T13:30:00Z","next_close":"2018-04-02T20:00:00Z"}"#);
+
+   // WHEN - we get the clock from each of them
+   let open_alpaca = block_on(AlpacaBuilder::new("someKey", "someSecret", Endpoint::custom(&open)).build()).unwrap();
+   let closed_alpaca = block_on(AlpacaBuilder::new("someKey", "someSecret", Endpoint::custom(&closed)).build()).unwrap();
+
+   // THEN - each context uses its own server
+   assert!(block_on(Clock::get(&open_alpaca)).unwrap().is_open);
+   assert!(!block_on(Clock::get(&closed_alpaca)).unwrap().is_open);
+}
+
+#[test]
+fn endpoint_from_env() {
+   //! Ensure that the standard environment variables are used
+
+   // GIVEN - the environment set up for our mock server
+   let _m = common::build_mock("GET", "/v2/clock").create();
+   let _env = EnvGuard::set(&[
+      ("APCA_API_KEY_ID", "someKey".to_string()),
+      ("APCA_API_SECRET_KEY", "someSecret".to_string()),
+      ("APCA_API_BASE_URL", format!("{}/", mockito::server_url()))
+   ]);
+
+   // WHEN - we build from it
+   let alpaca = block_on(Alpaca::from_env()).unwrap();
+
+   // THEN - it's pointing at the mock server for both REST and the stream
+   let endpoint = Endpoint::Custom { rest: mockito::server_url(), stream: format!("{}/stream", mockito::server_url().replacen("http", "ws", 1)) };
+   assert_eq!(&endpoint, alpaca.endpoint());
+}
+
+#[test]
+fn retry_server_errors() {
+   //! Ensure that idempotent calls are retried, and that Alpaca is reported down if the errors persist
+
+   // GIVEN - Alpaca failing every call
+   let alpaca = block_on(common::build_alpaca_with(|builder| builder.retry_policy(RetryPolicy::new(2).base_delay(Duration::from_millis(1)))));
+   let m = common::build_mock("GET", "/v2/account")
+      .with_status(503)
+      .expect(3)
+      .create();
+
+   // WHEN - we get our account
+   let error = block_on(Account::get(&alpaca)).unwrap_err();
+
+   // THEN - the call was tried three times before giving up
+   m.assert();
+   assert_eq!(ErrorKind::AlpacaDown, error.kind());
+   assert_eq!(Some(503), error.http_status());
+   assert!(error.is_retryable());
+}
+
+#[test]
+fn unreachable_is_network() {
+   //! Ensure that a call that can't reach Alpaca is reported as a network problem worth retrying
+
+   // GIVEN - nothing listening where Alpaca should be
+   let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+   let builder = AlpacaBuilder::new("someKey", "someSecret", Endpoint::custom(&format!("http://127.0.0.1:{}", port)))
+      .retry_policy(RetryPolicy::none());
+
+   // WHEN - we build the context
+   let error = block_on(builder.build()).err().unwrap();
+
+   // THEN - we get a retryable network error
+   assert_eq!(ErrorKind::Network, error.kind());
+   assert!(error.is_retryable());
+}
+
+#[test]
+fn retry_after_capped() {
+   //! Ensure that a long Retry-After from Alpaca is capped at the policy's maximum delay
+
+   // GIVEN - Alpaca rate limiting once and asking us to wait an hour
+   let alpaca = block_on(common::build_alpaca_with(|builder| builder.retry_policy(RetryPolicy::new(1).max_delay(Duration::from_millis(10)))));
+   let _limited = common::build_mock("GET", "/v2/account")
+      .with_header("Retry-After", "3600")
+      .with_status(429)
+      .expect(1)
+      .create();
+   let _account = common::build_mock("GET", "/v2/account")
+      .with_body(fs::read_to_string("tests/account_data/valid.json").unwrap())
+      .create();
+
+   // WHEN - we get our account
+   let started = Instant::now();
+   let result = block_on(Account::get(&alpaca));
+
+   // THEN - the retry went ahead without waiting the hour
+   assert!(result.is_ok());
+   assert!(started.elapsed() < Duration::from_secs(5));
+}
+
+#[test]
+fn no_retry_new_order() {
+   //! Ensure that orders without a client order ID are never sent twice
+
+   // GIVEN - Alpaca failing every call
+   let alpaca = block_on(common::build_alpaca_with(|builder| builder.retry_policy(RetryPolicy::new(2).base_delay(Duration::from_millis(1)))));
+   let m = common::build_mock("POST", "/v2/orders")
+      .with_status(503)
+      .expect(1)
+      .create();
+
+   // WHEN - we place an order
+   let result = block_on(Order::buy("AAPL", 1.0, OrderType::Market, TimeInForce::DAY).place(&alpaca));
+
+   // THEN - the order was sent once
+   m.assert();
+   assert!(result.is_err());
+}
+
+#[test]
+fn rate_limit_status() {
+   //! Ensure that the rate limit headers are tracked
+
+   // GIVEN - Alpaca reporting our rate limit
+   let alpaca = block_on(common::build_alpaca());
+   let _m = common::build_mock("GET", "/v2/clock")
+      .with_header("X-RateLimit-Limit", "200")
+      .with_header("X-RateLimit-Remaining", "150")
+      .with_header("X-RateLimit-Reset", "1600000000")
+      .with_body(fs::read_to_string("tests/clock_data/valid.json").unwrap())
+      .create();
+
+   // WHEN - we make a call
+   block_on(Clock::get(&alpaca)).unwrap();
+
+   // THEN - the status is available
+   let status = alpaca.rate_limit_status().unwrap();
+   assert_eq!(200, status.limit);
+   assert_eq!(150, status.remaining);
+   assert_eq!(Utc.timestamp_opt(1_600_000_000, 0).unwrap(), status.reset);
+}
+
+#[test]
+fn rate_limit_status_ignores_bad_headers() {
+   //! Ensure that rate limit headers that aren't valid counts are ignored rather than wrapped
+
+   // GIVEN - Alpaca reporting a negative number of calls remaining
+   let alpaca = block_on(common::build_alpaca());
+   let _m = common::build_mock("GET", "/v2/clock")
+      .with_header("X-RateLimit-Limit", "200")
+      .with_header("X-RateLimit-Remaining", "-1")
+      .with_header("X-RateLimit-Reset", "1600000000")
+      .with_body(fs::read_to_string("tests/clock_data/valid.json").unwrap())
+      .create();
+
+   // WHEN - we make a call
+   block_on(Clock::get(&alpaca)).unwrap();
+
+   // THEN - there is no status
+   assert_eq!(None, alpaca.rate_limit_status());
+}
+
+#[test]
+fn rate_limiter_queues_calls() {
+   //! Ensure that calls over the client side limit wait rather than being sent
+
+   // GIVEN - a limit of one call every 100ms (which the build check has already used)
+   let alpaca = block_on(common::build_alpaca_with(|builder| builder.rate_limiter(RateLimiter::new(1, Duration::from_millis(100)))));
+   let m = common::build_mock("GET", "/v2/clock")
+      .with_body(fs::read_to_string("tests/clock_data/valid.json").unwrap())
+      .expect(3)
+      .create();
+
+   // WHEN - we make three calls
+   let start = Instant::now();
+   for _ in 0..3 { block_on(Clock::get(&alpaca)).unwrap(); }
+
+   // THEN - they were all sent, but spaced out by the limit
+   m.assert();
+   assert!(start.elapsed() >= Duration::from_millis(250));
+}
+
+#[test]
+fn rate_limiter_sends_cancels_first() {
+   //! Ensure that a cancel waiting on the limit goes ahead of calls that were waiting before it
+
+   // GIVEN - a limit of one call every 200ms (which the build check has already used)
+   let alpaca = block_on(common::build_alpaca_with(|builder| builder.rate_limiter(RateLimiter::new(1, Duration::from_millis(200)))));
+   let _clock = common::build_mock("GET", "/v2/clock")
+      .with_body(fs::read_to_string("tests/clock_data/valid.json").unwrap())
+      .create();
+   let _close = common::build_mock("DELETE", "/v2/positions/AAPL")
+      .with_body(fs::read_to_string("tests/order_data/valid.json").unwrap())
+      .create();
+
+   // WHEN - a call is queued, and then a close
+   let sent = Mutex::new(Vec::new());
+   block_on(async {
+      futures::join!(
+         async {
+            Clock::get(&alpaca).await.unwrap();
+            sent.lock().unwrap().push("GET");
+         },
+         async {
+            tokio::time::delay_for(Duration::from_millis(20)).await;
+            Position::close(&alpaca, "AAPL").await.unwrap();
+            sent.lock().unwrap().push("DELETE");
+         }
+      )
+   });
+
+   // THEN - the close was sent first
+   assert_eq!(vec!["DELETE", "GET"], sent.into_inner().unwrap());
+}
+
+#[test]
+fn rate_limiter_waits_for_reset() {
+   //! Ensure that calls wait for the reset once Alpaca says there are none remaining
+
+   // GIVEN - Alpaca saying we have used up our calls until the next window
+   let alpaca = block_on(common::build_alpaca_with(|builder| builder.rate_limiter(RateLimiter::new(100, Duration::from_secs(1)))));
+   let reset = Utc::now().timestamp() + 2;
+   let _exhausted = common::build_mock("GET", "/v2/clock")
+      .with_header("X-RateLimit-Limit", "200")
+      .with_header("X-RateLimit-Remaining", "0")
+      .with_header("X-RateLimit-Reset", &reset.to_string())
+      .with_body(fs::read_to_string("tests/clock_data/valid.json").unwrap())
+      .expect(1)
+      .create();
+   let _m = common::build_mock("GET", "/v2/clock")
+      .with_body(fs::read_to_string("tests/clock_data/valid.json").unwrap())
+      .create();
+   block_on(Clock::get(&alpaca)).unwrap();
+
+   // WHEN - we make another call
+   block_on(Clock::get(&alpaca)).unwrap();
+
+   // THEN - it was held until the reset
+   assert!(Utc::now().timestamp() >= reset);
+}
@@ -0,0 +1,93 @@
+use alpaca_finance::{ Asset, AssetClass, AssetFilter, AssetStatus, Exchange };
+use mockito::{ Matcher, Mock };
+use std::fs::File;
+use std::io::prelude::*;
+use tokio_test::block_on;
+
+mod common;
+
+async fn base_mock(test_name: &str, mock: Mock) -> std::io::Result<Mock> {
+   // Load the simulated Alpaca data we want to test against
+   let mut file = File::open(format!("tests/asset_data/{}.json", test_name))?;
+   let mut contents = String::new();
+   file.read_to_string(&mut contents)?;
+
+   Ok(mock.with_header("content-type", "application/json")
+      .with_body(&contents)
+      .with_status(200))
+}
+
+#[test]
+fn get() {
+   //! Ensure that we can look up a single asset
+
+   // GIVEN - a valid asset
+   let alpaca = block_on(common::build_alpaca());
+   let _m = block_on(base_mock("valid", common::build_mock("GET", "/v2/assets/AAPL"))).unwrap().create();
+
+   // WHEN - we look it up
+   let asset = block_on(Asset::get(&alpaca, "AAPL")).unwrap();
+
+   // THEN - we get the results we expect
+   assert_eq!("AAPL", asset.symbol);
+   assert_eq!(AssetClass::UsEquity, asset.class);
+   assert_eq!(Exchange::NASDAQ, asset.exchange);
+   assert_eq!(AssetStatus::Active, asset.status);
+   assert!(asset.is_tradable);
+   assert!(asset.is_shortable);
+   assert!(asset.is_easy_to_borrow);
+   assert!(asset.is_fractionable);
+}
+
+#[test]
+fn list_filtered() {
+   //! Ensure that the filters are passed on to Alpaca
+
+   // GIVEN - a list of active NASDAQ assets
+   let alpaca = block_on(common::build_alpaca());
+   let mock = common::build_mock("GET", "/v2/assets")
+      .match_query(Matcher::AllOf(vec![
+         Matcher::UrlEncoded("status".into(), "active".into()),
+         Matcher::UrlEncoded("exchange".into(), "NASDAQ".into())
+      ]));
+   let _m = block_on(base_mock("valid_list", mock)).unwrap().create();
+
+   // WHEN - we list them
+   let filter = AssetFilter::default().status(AssetStatus::Active).exchange(Exchange::NASDAQ);
+   let assets = block_on(Asset::list(&alpaca, &filter)).unwrap();
+
+   // THEN - we get the results we expect
+   assert_eq!(1, assets.len());
+   assert_eq!("AAPL", assets[0].symbol);
+}
+
+#[test]
+fn get_unknown_exchange() {
+   //! Ensure that an asset on an exchange we don't know about can still be looked up
+
+   // GIVEN - a crypto asset on a crypto venue
+   let alpaca = block_on(common::build_alpaca());
+   let _m = block_on(base_mock("crypto", common::build_mock("GET", "/v2/assets/BTCUSD"))).unwrap().create();
+
+   // WHEN - we look it up
+   let asset = block_on(Asset::get(&alpaca, "BTCUSD")).unwrap();
+
+   // THEN - the exchange is unknown rather than the call failing
+   assert_eq!(AssetClass::Crypto, asset.class);
+   assert_eq!(Exchange::Unknown, asset.exchange);
+}
+
+#[test]
+fn get_unknown_class() {
+   //! Ensure that an asset of a class we don't know about can still be looked up
+
+   // GIVEN - an option contract
+   let alpaca = block_on(common::build_alpaca());
+   let _m = block_on(base_mock("option", common::build_mock("GET", "/v2/assets/AAPL250117C00150000"))).unwrap().create();
+
+   // WHEN - we look it up
+   let asset = block_on(Asset::get(&alpaca, "AAPL250117C00150000")).unwrap();
+
+   // THEN - the class is unknown rather than the call failing
+   assert_eq!(AssetClass::Unknown, asset.class);
+}
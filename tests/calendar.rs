@@ -0,0 +1,44 @@
+use alpaca_finance::Calendar;
+use chrono::{ NaiveDate, NaiveTime };
+use mockito::{ Matcher, Mock };
+use std::fs::File;
+use std::io::prelude::*;
+use tokio_test::block_on;
+
+mod common;
+
+async fn base_mock(test_name: &str, mock: Mock) -> std::io::Result<Mock> {
+   // Load the simulated Alpaca data we want to test against
+   let mut file = File::open(format!("tests/calendar_data/{}.json", test_name))?;
+   let mut contents = String::new();
+   file.read_to_string(&mut contents)?;
+
+   Ok(mock.with_header("content-type", "application/json")
+      .with_body(&contents)
+      .with_status(200))
+}
+
+#[test]
+fn get_calendar() {
+   //! Ensure that we can load the trading days in a date range
+
+   // GIVEN - the calendar around thanksgiving
+   let alpaca = block_on(common::build_alpaca());
+   let mock = common::build_mock("GET", "/v2/calendar")
+      .match_query(Matcher::AllOf(vec![
+         Matcher::UrlEncoded("start".into(), "2018-11-21".into()),
+         Matcher::UrlEncoded("end".into(), "2018-11-23".into())
+      ]));
+   let _m = block_on(base_mock("valid", mock)).unwrap().create();
+
+   // WHEN - we get the calendar
+   let start = NaiveDate::from_ymd_opt(2018, 11, 21).unwrap();
+   let end = NaiveDate::from_ymd_opt(2018, 11, 23).unwrap();
+   let days = block_on(Calendar::get(&alpaca, start, end)).unwrap();
+
+   // THEN - we get only the trading days, including the early close
+   assert_eq!(2, days.len());
+   assert_eq!(NaiveDate::from_ymd_opt(2018, 11, 23).unwrap(), days[1].date);
+   assert_eq!(NaiveTime::from_hms_opt(9, 30, 0).unwrap(), days[1].open);
+   assert_eq!(NaiveTime::from_hms_opt(13, 0, 0).unwrap(), days[1].close);
+}
@@ -0,0 +1,36 @@
+use alpaca_finance::Clock;
+use chrono::{ TimeZone, Utc };
+use mockito::Mock;
+use std::fs::File;
+use std::io::prelude::*;
+use tokio_test::block_on;
+
+mod common;
+
+async fn base_mock(test_name: &str, mock: Mock) -> std::io::Result<Mock> {
+   // Load the simulated Alpaca data we want to test against
+   let mut file = File::open(format!("tests/clock_data/{}.json", test_name))?;
+   let mut contents = String::new();
+   file.read_to_string(&mut contents)?;
+
+   Ok(mock.with_header("content-type", "application/json")
+      .with_body(&contents)
+      .with_status(200))
+}
+
+#[test]
+fn get_clock() {
+   //! Ensure that we can load the market clock
+
+   // GIVEN - the market clock
+   let alpaca = block_on(common::build_alpaca());
+   let _m = block_on(base_mock("valid", common::build_mock("GET", "/v2/clock"))).unwrap().create();
+
+   // WHEN - we get the clock
+   let clock = block_on(Clock::get(&alpaca)).unwrap();
+
+   // THEN - we get the times converted to UTC
+   assert!(clock.is_open);
+   assert_eq!(Utc.with_ymd_and_hms(2018, 4, 2, 13, 30, 0).unwrap(), clock.next_open);
+   assert_eq!(Utc.with_ymd_and_hms(2018, 4, 1, 20, 0, 0).unwrap(), clock.next_close);
+}
@@ -1,19 +1,31 @@
-use alpaca_finance::Alpaca;
+#![allow(dead_code)]
+
+use alpaca_finance::{ Alpaca, AlpacaBuilder, Endpoint };
 use mockito::{ mock, Mock };
-use std::env;
 
-const KEY_ID: &'static str = "someKey";
-const SECRET: &'static str = "someSecret";
+const KEY_ID: &str = "someKey";
+const SECRET: &str = "someSecret";
 
 pub async fn build_alpaca() -> Alpaca {
-   // Tell the actual code to use a test URL rather than the live one
-   env::set_var("TEST_URL", mockito::server_url());
+   build_alpaca_with(|builder| builder).await
+}
 
+pub async fn build_alpaca_with<F: FnOnce(AlpacaBuilder) -> AlpacaBuilder>(configure: F) -> Alpaca {
    // Set up the auth check
    let _validate = build_mock("GET", "/v2/clock").create();
    
-   // and build our Alpaca client
-   Alpaca::live(KEY_ID, SECRET).await.unwrap()
+   // and build our Alpaca client against the mock server rather than the live one
+   let builder = AlpacaBuilder::new(KEY_ID, SECRET, Endpoint::custom(&mockito::server_url()));
+   configure(builder).build().await.unwrap()
+}
+
+pub async fn build_alpaca_streaming(stream_url: &str) -> Alpaca {
+   // Set up the auth check
+   let _validate = build_mock("GET", "/v2/clock").create();
+
+   // and build our Alpaca client against the mock server, with the stream on its own server
+   let endpoint = Endpoint::Custom { rest: mockito::server_url(), stream: stream_url.to_string() };
+   AlpacaBuilder::new(KEY_ID, SECRET, endpoint).build().await.unwrap()
 }
 
 pub fn build_mock(verb: &'static str, path: &'static str) -> Mock {
@@ -1,7 +1,13 @@
-use alpaca_finance::{ Order };
-use mockito::Mock;
+use alpaca_finance::{
+   ClientOrderIdGenerator, ErrorKind, Order, OrderClass, OrderQueryStatus, OrderSide, OrderType, PrefixedIdGenerator, RetryPolicy, StopLoss,
+   TakeProfit, TimeInForce
+};
+use chrono::{ TimeZone, Utc };
+use futures::TryStreamExt;
+use mockito::{ Matcher, Mock };
 use std::fs::File;
 use std::io::prelude::*;
+use std::time::Duration;
 use tokio_test::block_on;
 
 mod common;
@@ -32,4 +38,365 @@ fn get_open() {
    assert_eq!(1, orders.len());
    assert_eq!(orders[0].id, "904837e3-3b76-47ec-b432-046db621571b");
    assert_eq!(orders[0].client_order_id, "904837e3-3b76-47ec-b432-046db621571b");
-}
\ No newline at end of file
+}
+
+#[test]
+fn get_by_client_order_id() {
+   //! Ensure that we can look up an order by our own ID
+
+   // GIVEN - an order placed with a client order ID
+   let alpaca = block_on(common::build_alpaca());
+   let mock = common::build_mock("GET", "/v2/orders:by_client_order_id")
+      .match_query(Matcher::UrlEncoded("client_order_id".into(), "904837e3-3b76-47ec-b432-046db621571b".into()));
+   let _m = block_on(base_mock("valid", mock)).unwrap().create();
+
+   // WHEN - we look up the order
+   let order = block_on(Order::get_by_client_order_id(&alpaca, "904837e3-3b76-47ec-b432-046db621571b")).unwrap();
+
+   // THEN - we get the order we expect
+   assert_eq!("904837e3-3b76-47ec-b432-046db621571b", order.id);
+   assert_eq!("AAPL", order.symbol);
+}
+
+#[test]
+fn get_not_found() {
+   //! Ensure that a missing order is reported by the order lookup, not as a generic not found
+
+   // GIVEN - no order with the ID
+   let alpaca = block_on(common::build_alpaca());
+   let _m = common::build_mock("GET", "/v2/orders/missing")
+      .with_body(r#"{"code":40410000,"message":"order not found"}"#)
+      .with_status(404)
+      .create();
+
+   // WHEN - we look up the order
+   let error = block_on(Order::get(&alpaca, "missing")).unwrap_err();
+
+   // THEN - we get a not found error for the order
+   assert_eq!(ErrorKind::NotFound, error.kind());
+   assert_eq!(Some(404), error.http_status());
+   assert_eq!("The order 'missing' was not found", error.to_string());
+}
+
+#[test]
+fn update_not_found() {
+   //! Ensure that replacing an order that has gone is reported the same way as looking it up
+
+   // GIVEN - an order that Alpaca no longer knows about
+   let alpaca = block_on(common::build_alpaca());
+   let _get = block_on(base_mock("valid", common::build_mock("GET", "/v2/orders/904837e3-3b76-47ec-b432-046db621571b"))).unwrap().create();
+   let order = block_on(Order::get(&alpaca, "904837e3-3b76-47ec-b432-046db621571b")).unwrap();
+   let _m = common::build_mock("PATCH", "/v2/orders/904837e3-3b76-47ec-b432-046db621571b")
+      .with_body(r#"{"code":40410000,"message":"order not found"}"#)
+      .with_status(404)
+      .create();
+
+   // WHEN - we replace the order
+   let error = block_on(order.update().qty(5.0).place(&alpaca)).unwrap_err();
+
+   // THEN - we get a not found error for the order
+   assert_eq!(ErrorKind::NotFound, error.kind());
+   assert_eq!("The order '904837e3-3b76-47ec-b432-046db621571b' was not found", error.to_string());
+}
+
+#[test]
+fn query_pages() {
+   //! Ensure that a query pages through the history without repeating orders
+
+   // GIVEN - three orders spread over pages of two, with one order repeated on the second page
+   let alpaca = block_on(common::build_alpaca());
+   let mut mocks = vec![];
+   for (page, until) in [("history_1", "2018-10-05T05:49:10Z"), ("history_2", "2018-10-05T05:49:02.000001Z"), ("history_3", "2018-10-05T05:49:01.000001Z")].iter() {
+      let mock = common::build_mock("GET", "/v2/orders")
+         .match_query(Matcher::AllOf(vec![
+            Matcher::UrlEncoded("status".into(), "all".into()),
+            Matcher::UrlEncoded("limit".into(), "2".into()),
+            Matcher::UrlEncoded("until".into(), until.to_string())
+         ]));
+      mocks.push(block_on(base_mock(page, mock)).unwrap().expect(1).create());
+   }
+
+   // WHEN - we run the query
+   let orders: Vec<Order> = block_on(Order::query()
+      .status(OrderQueryStatus::All)
+      .until(Utc.with_ymd_and_hms(2018, 10, 5, 5, 49, 10).unwrap())
+      .page_size(2)
+      .fetch(&alpaca)
+      .try_collect()).unwrap();
+
+   // THEN - we get every order once, newest first
+   let ids: Vec<&str> = orders.iter().map(|order| &order.id[..1]).collect();
+   assert_eq!(vec!["a", "b", "c"], ids);
+   mocks.iter().for_each(|mock| mock.assert());
+}
+
+#[test]
+fn place_duplicate_client_order_id() {
+   //! Ensure that reusing a client order ID gives back the order that already has it
+
+   // GIVEN - an order already placed with our client order ID
+   let alpaca = block_on(common::build_alpaca());
+   let _place = common::build_mock("POST", "/v2/orders")
+      .match_body(Matcher::PartialJsonString(r#"{"client_order_id":"904837e3-3b76-47ec-b432-046db621571b"}"#.to_string()))
+      .with_body(r#"{"code":40010001,"message":"client_order_id must be unique"}"#)
+      .with_status(422)
+      .create();
+   let lookup = common::build_mock("GET", "/v2/orders:by_client_order_id")
+      .match_query(Matcher::UrlEncoded("client_order_id".into(), "904837e3-3b76-47ec-b432-046db621571b".into()));
+   let _lookup = block_on(base_mock("valid", lookup)).unwrap().create();
+
+   // WHEN - we place the order again
+   let error = block_on(Order::buy("AAPL", 15.0, OrderType::Market, TimeInForce::DAY)
+      .client_order_id("904837e3-3b76-47ec-b432-046db621571b")
+      .place(&alpaca)).unwrap_err();
+
+   // THEN - the error carries the existing order
+   let existing = error.existing_order().unwrap();
+   assert_eq!("904837e3-3b76-47ec-b432-046db621571b", existing.id);
+}
+
+#[test]
+fn place_duplicate_client_order_id_by_code() {
+   //! Ensure that a reused client order ID is recognised by Alpaca's code, whatever the message says
+
+   // GIVEN - an order already placed with our client order ID, and Alpaca wording the rejection differently
+   let alpaca = block_on(common::build_alpaca());
+   let _place = common::build_mock("POST", "/v2/orders")
+      .with_body(r#"{"code":40010001,"message":"an order with this client order id already exists"}"#)
+      .with_status(422)
+      .create();
+   let lookup = common::build_mock("GET", "/v2/orders:by_client_order_id")
+      .match_query(Matcher::UrlEncoded("client_order_id".into(), "904837e3-3b76-47ec-b432-046db621571b".into()));
+   let _lookup = block_on(base_mock("valid", lookup)).unwrap().create();
+
+   // WHEN - we place the order again
+   let error = block_on(Order::buy("AAPL", 15.0, OrderType::Market, TimeInForce::DAY)
+      .client_order_id("904837e3-3b76-47ec-b432-046db621571b")
+      .place(&alpaca)).unwrap_err();
+
+   // THEN - the error is a duplicate carrying the existing order
+   assert_eq!(ErrorKind::DuplicateClientOrderId, error.kind());
+   assert_eq!("904837e3-3b76-47ec-b432-046db621571b", error.existing_order().unwrap().id);
+}
+
+#[test]
+fn place_retried_after_reaching_alpaca() {
+   //! Ensure that an order placed by a try that failed is returned when the retry is rejected as a duplicate
+
+   // GIVEN - Alpaca failing the first try after placing the order, then rejecting the retry
+   let alpaca = block_on(common::build_alpaca_with(|builder| builder.retry_policy(RetryPolicy::new(2).base_delay(Duration::from_millis(1)))));
+   let failed = common::build_mock("POST", "/v2/orders")
+      .with_status(503)
+      .expect(1)
+      .create();
+   let duplicate = common::build_mock("POST", "/v2/orders")
+      .with_body(r#"{"code":40010001,"message":"client_order_id must be unique"}"#)
+      .with_status(422)
+      .expect(1)
+      .create();
+   let lookup = common::build_mock("GET", "/v2/orders:by_client_order_id")
+      .match_query(Matcher::UrlEncoded("client_order_id".into(), "904837e3-3b76-47ec-b432-046db621571b".into()));
+   let _lookup = block_on(base_mock("valid", lookup)).unwrap().create();
+
+   // WHEN - we place the order
+   let order = block_on(Order::buy("AAPL", 15.0, OrderType::Market, TimeInForce::DAY)
+      .client_order_id("904837e3-3b76-47ec-b432-046db621571b")
+      .place(&alpaca)).unwrap();
+
+   // THEN - we get the order that was placed
+   failed.assert();
+   duplicate.assert();
+   assert_eq!("904837e3-3b76-47ec-b432-046db621571b", order.id);
+}
+
+#[test]
+fn place_rejected_with_api_error() {
+   //! Ensure that the error Alpaca sends back when rejecting an order is kept
+
+   // GIVEN - Alpaca rejecting the order as wash trading
+   let alpaca = block_on(common::build_alpaca());
+   let _m = common::build_mock("POST", "/v2/orders")
+      .with_body(r#"{"code":40310100,"message":"potential wash trade detected"}"#)
+      .with_status(422)
+      .create();
+
+   // WHEN - we place the order
+   let error = block_on(Order::sell("AAPL", 15.0, OrderType::Market, TimeInForce::DAY).place(&alpaca)).unwrap_err();
+
+   // THEN - the error has the code, status and message from Alpaca
+   assert_eq!(ErrorKind::Invalid, error.kind());
+   assert_eq!(Some(422), error.http_status());
+   assert_eq!(Some(40310100), error.api_code());
+   assert!(!error.is_retryable());
+   assert!(error.to_string().contains("potential wash trade detected"));
+}
+
+#[test]
+fn place_forbidden_with_api_error() {
+   //! Ensure that the reason Alpaca refuses an order is kept
+
+   // GIVEN - Alpaca refusing the order for a lack of buying power
+   let alpaca = block_on(common::build_alpaca());
+   let _m = common::build_mock("POST", "/v2/orders")
+      .with_body(r#"{"code":40310000,"message":"insufficient buying power"}"#)
+      .with_status(403)
+      .create();
+
+   // WHEN - we place the order
+   let error = block_on(Order::buy("AAPL", 15.0, OrderType::Market, TimeInForce::DAY).place(&alpaca)).unwrap_err();
+
+   // THEN - the error has the code, status and message from Alpaca
+   assert_eq!(ErrorKind::Forbidden, error.kind());
+   assert_eq!(Some(403), error.http_status());
+   assert_eq!(Some(40310000), error.api_code());
+   assert!(error.to_string().contains("insufficient buying power"));
+}
+
+#[test]
+fn prefixed_client_order_ids() {
+   //! Ensure that generated client order IDs are unique and tagged
+
+   // GIVEN - a generator for our strategy
+   let ids = PrefixedIdGenerator::new("momentum");
+
+   // WHEN - we generate a couple of IDs
+   let first = ids.generate();
+   let second = ids.generate();
+
+   // THEN - they are different and prefixed
+   assert!(first.starts_with("momentum-"));
+   assert!(second.starts_with("momentum-"));
+   assert_ne!(first, second);
+}
+
+#[test]
+fn get_bracket_legs() {
+   //! Ensure that the legs of a bracket order are loaded
+
+   // GIVEN - a bracket order with a take profit leg
+   let alpaca = block_on(common::build_alpaca());
+   let mock = common::build_mock("GET", "/v2/orders/904837e3-3b76-47ec-b432-046db621571b");
+   let _m = block_on(base_mock("valid_bracket", mock)).unwrap().create();
+
+   // WHEN - we get the order
+   let order = block_on(Order::get(&alpaca, "904837e3-3b76-47ec-b432-046db621571b")).unwrap();
+
+   // THEN - the legs are there
+   assert_eq!(OrderClass::Bracket, order.order_class);
+   let legs = order.legs.unwrap();
+   assert_eq!(1, legs.len());
+   assert_eq!(OrderSide::Sell, legs[0].side);
+   assert_eq!(OrderType::Limit, legs[0].order_type);
+}
+
+#[test]
+#[should_panic(expected = "OrderInvalid")]
+fn place_bracket_inverted_exits() {
+   //! Ensure that a bracket order whose take profit is below its stop loss is never sent
+
+   // GIVEN - a long entry taking profit below where it stops the loss
+   let alpaca = block_on(common::build_alpaca());
+   let order = Order::buy("AAPL", 15.0, OrderType::Limit, TimeInForce::DAY)
+      .limit_price(100.0)
+      .order_class(OrderClass::Bracket)
+      .take_profit(TakeProfit::new(95.0))
+      .stop_loss(StopLoss::new(105.0));
+
+   // WHEN - we place it
+   block_on(order.place(&alpaca)).unwrap();
+
+   // THEN - we get an error
+}
+
+#[test]
+fn get_trailing_stop() {
+   //! Ensure that the trail and high-water mark of a trailing stop order are loaded
+
+   // GIVEN - a trailing stop order trailing by 1%
+   let alpaca = block_on(common::build_alpaca());
+   let mock = common::build_mock("GET", "/v2/orders/904837e3-3b76-47ec-b432-046db621571b");
+   let _m = block_on(base_mock("valid_trailing", mock)).unwrap().create();
+
+   // WHEN - we get the order
+   let order = block_on(Order::get(&alpaca, "904837e3-3b76-47ec-b432-046db621571b")).unwrap();
+
+   // THEN - we get the trail and high-water mark
+   assert_eq!(OrderType::TrailingStop, order.order_type);
+   assert_eq!(Some(1.0), order.trail_percent);
+   assert_eq!(None, order.trail_price);
+   assert_eq!(Some(110.5), order.hwm);
+}
+
+#[test]
+#[should_panic(expected = "OrderInvalid")]
+fn place_trailing_stop_both_trails() {
+   //! Ensure that a trailing stop order can't trail by both price and percent
+
+   // GIVEN - a trailing stop with both trails set
+   let alpaca = block_on(common::build_alpaca());
+   let order = Order::sell("AAPL", 15.0, OrderType::TrailingStop, TimeInForce::DAY)
+      .trail_price(1.0)
+      .trail_percent(1.0);
+
+   // WHEN - we place it
+   block_on(order.place(&alpaca)).unwrap();
+
+   // THEN - we get an error
+}
+
+#[test]
+fn get_notional() {
+   //! Ensure that a notional order with a fractional fill is loaded
+
+   // GIVEN - a $500 order that has partially filled
+   let alpaca = block_on(common::build_alpaca());
+   let mock = common::build_mock("GET", "/v2/orders/904837e3-3b76-47ec-b432-046db621571b");
+   let _m = block_on(base_mock("valid_notional", mock)).unwrap().create();
+
+   // WHEN - we get the order
+   let order = block_on(Order::get(&alpaca, "904837e3-3b76-47ec-b432-046db621571b")).unwrap();
+
+   // THEN - we get the notional amount and the fractional fill
+   assert_eq!(None, order.qty);
+   assert_eq!(Some(500.0), order.notional);
+   assert_eq!(2.751297, order.filled_qty);
+}
+
+#[test]
+#[should_panic(expected = "OrderInvalid")]
+fn place_notional_limit() {
+   //! Ensure that a notional order can only be placed at market
+
+   // GIVEN - a notional limit order
+   let alpaca = block_on(common::build_alpaca());
+   let order = Order::buy_notional("AAPL", 500.0, OrderType::Limit, TimeInForce::DAY)
+      .limit_price(100.0);
+
+   // WHEN - we place it
+   block_on(order.place(&alpaca)).unwrap();
+
+   // THEN - we get an error
+}
+
+#[test]
+fn cancel_all() {
+   //! Ensure that we report the result of canceling each order
+
+   // GIVEN - one order that cancels, one that is already filled and one that hit a server error
+   let alpaca = block_on(common::build_alpaca());
+   let _m = block_on(base_mock("cancel_all", common::build_mock("DELETE", "/v2/orders"))).unwrap()
+      .with_status(207)
+      .create();
+
+   // WHEN - we cancel all orders
+   let results = block_on(Order::cancel_all(&alpaca)).unwrap();
+
+   // THEN - we get the result for each order
+   assert_eq!(3, results.len());
+   assert!(results["904837e3-3b76-47ec-b432-046db621571b"].is_ok());
+   let error = results["b04837e3-3b76-47ec-b432-046db621571b"].as_ref().unwrap_err();
+   assert!(format!("{:?}", error).contains("OrderNotCancelable"));
+   let error = results["c04837e3-3b76-47ec-b432-046db621571b"].as_ref().unwrap_err();
+   assert_eq!(ErrorKind::AlpacaDown, error.kind());
+   assert_eq!(Some(500), error.http_status());
+}
@@ -0,0 +1,113 @@
+use alpaca_finance::{ ErrorKind, Position, PositionSide, RetryPolicy };
+use mockito::Mock;
+use std::fs::File;
+use std::io::prelude::*;
+use tokio_test::block_on;
+
+mod common;
+
+async fn base_mock(test_name: &str, mock: Mock) -> std::io::Result<Mock> {
+   // Load the simulated Alpaca data we want to test against
+   let mut file = File::open(format!("tests/position_data/{}.json", test_name))?;
+   let mut contents = String::new();
+   file.read_to_string(&mut contents)?;
+
+   Ok(mock.with_header("content-type", "application/json")
+      .with_body(&contents)
+      .with_status(200))
+}
+
+#[test]
+fn get_all() {
+   //! Ensure that we can load all open positions
+
+   // GIVEN - a valid open position
+   let alpaca = block_on(common::build_alpaca());
+   let _m = block_on(base_mock("valid_all", common::build_mock("GET", "/v2/positions"))).unwrap().create();
+
+   // WHEN - we get our open positions
+   let positions = block_on(Position::get_all(&alpaca)).unwrap();
+
+   // THEN - we get the results we expect
+   assert_eq!(1, positions.len());
+   assert_eq!("AAPL", positions[0].symbol);
+   assert_eq!(5.0, positions[0].qty);
+   assert_eq!(PositionSide::Long, positions[0].side);
+   assert_eq!(100.0, positions[0].avg_entry_price);
+   assert_eq!(119.0, positions[0].last_day_price);
+}
+
+#[test]
+#[should_panic(expected = "PositionNotFound")]
+fn get_not_found() {
+   //! Ensure that we fail gracefully when there is no position in the symbol
+
+   // GIVEN - no position in MSFT
+   let alpaca = block_on(common::build_alpaca());
+   let _m = common::build_mock("GET", "/v2/positions/MSFT")
+      .with_body(r#"{"code":40410000,"message":"position does not exist"}"#)
+      .with_status(404)
+      .create();
+
+   // WHEN - we get the position
+   block_on(Position::get(&alpaca, "MSFT")).unwrap();
+
+   // THEN - we get an error
+}
+
+#[test]
+fn get_rate_limited() {
+   //! Ensure that a failure the position lookup doesn't handle itself is still classified
+
+   // GIVEN - Alpaca rate limiting us, with no retries
+   let alpaca = block_on(common::build_alpaca_with(|builder| builder.retry_policy(RetryPolicy::none())));
+   let _m = common::build_mock("GET", "/v2/positions/MSFT")
+      .with_body(r#"{"message":"too many requests."}"#)
+      .with_status(429)
+      .create();
+
+   // WHEN - we get the position
+   let error = block_on(Position::get(&alpaca, "MSFT")).unwrap_err();
+
+   // THEN - we get a rate limited error
+   assert_eq!(ErrorKind::RateLimited, error.kind());
+}
+
+#[test]
+fn close_not_closable() {
+   //! Ensure that a position that can't be closed is reported as such, not as an invalid call
+
+   // GIVEN - a position Alpaca won't close
+   let alpaca = block_on(common::build_alpaca());
+   let _m = common::build_mock("DELETE", "/v2/positions/AAPL")
+      .with_body(r#"{"code":42210000,"message":"insufficient qty available for order"}"#)
+      .with_status(422)
+      .create();
+
+   // WHEN - we close the position
+   let error = block_on(Position::close(&alpaca, "AAPL")).unwrap_err();
+
+   // THEN - we get the position's error rather than the generic one
+   assert_eq!(ErrorKind::NotCancelable, error.kind());
+   assert_eq!(Some(422), error.http_status());
+   assert_eq!("The position in 'AAPL' cannot be closed", error.to_string());
+}
+
+#[test]
+fn close_all() {
+   //! Ensure that we report the result of closing each position
+
+   // GIVEN - one position that closes and one that hit a server error
+   let alpaca = block_on(common::build_alpaca());
+   let _m = block_on(base_mock("close_all", common::build_mock("DELETE", "/v2/positions"))).unwrap()
+      .with_status(207)
+      .create();
+
+   // WHEN - we close all positions
+   let results = block_on(Position::close_all(&alpaca)).unwrap();
+
+   // THEN - we get the results we expect
+   assert_eq!(2, results.len());
+   assert_eq!("AAPL", results["AAPL"].as_ref().unwrap().symbol);
+   assert_eq!(ErrorKind::AlpacaDown, results["MSFT"].as_ref().unwrap_err().kind());
+}
@@ -1,8 +1,22 @@
-use alpaca_finance::{ Order, OrderEvent };
+use alpaca_finance::{
+   ErrorKind, EventFilter, LagMetrics, LagPolicy, Order, OrderEvent, OrderEventType, Recorder, Replayer, ReplaySpeed,
+   RetryPolicy, Streamer, StreamHub, StreamKind, StreamMessage, StreamState
+};
+use futures::channel::oneshot;
+use futures::{ Future, SinkExt, StreamExt };
 use handlebars::{ no_escape, Handlebars };
 use serde_json::json;
 use std::fs::File;
 use std::io::prelude::*;
+use std::sync::Arc;
+use std::sync::atomic::{ AtomicBool, Ordering };
+use std::time::{ Duration, Instant };
+use tokio::net::{ TcpListener, TcpStream };
+use tokio_test::block_on;
+use tokio_tungstenite::{ accept_async, WebSocketStream };
+use tokio_tungstenite::tungstenite::Message;
+
+mod common;
 
 fn build_event(test_name: &str) -> String {
    let mut reg = Handlebars::new();
@@ -20,8 +34,47 @@ fn build_event(test_name: &str) -> String {
    reg.render_template(&template, &json!({ "order": order })).unwrap()
 }
 
+/// Wraps an event in the message Alpaca sends it in on the stream
+fn build_message(stream: &str, event: &str) -> Message {
+   Message::Text(format!(r#"{{"stream":"{}","data":{}}}"#, stream, event))
+}
+
+/// Starts a websocket server that runs the session for each connection, one after the other.  The
+/// session is given the number of the connection, counting from zero.
+async fn start_server<F, R>(session: F) -> String
+where
+   F: Fn(usize, WebSocketStream<TcpStream>) -> R + Send + Sync + 'static,
+   R: Future<Output = ()> + Send
+{
+   let mut listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+   let url = format!("ws://{}/stream", listener.local_addr().unwrap());
+
+   tokio::spawn(async move {
+      let mut connection = 0;
+      while let Ok((stream, _)) = listener.accept().await {
+         session(connection, accept_async(stream).await.unwrap()).await;
+         connection += 1;
+      }
+   });
+   url
+}
+
+/// Answers the authenticate and listen messages like Alpaca does, listening on the streams asked for.
+/// Returns the streams.
+async fn set_up(socket: &mut WebSocketStream<TcpStream>) -> Vec<String> {
+   let auth = socket.next().await.unwrap().unwrap();
+   assert!(auth.to_string().contains("authenticate"));
+   socket.send(build_message("authorization", r#"{"action":"authenticate","status":"authorized"}"#)).await.unwrap();
+
+   let listen = serde_json::from_str::<serde_json::Value>(&socket.next().await.unwrap().unwrap().to_string()).unwrap();
+   let streams = listen["data"]["streams"].clone();
+   socket.send(build_message("listening", &json!({ "streams": streams }).to_string())).await.unwrap();
+
+   serde_json::from_value(streams).unwrap()
+}
+
 fn validate_order(order: Order) {
-   assert_eq!(15, order.qty);
+   assert_eq!(Some(15.0), order.qty);
    assert_eq!("AAPL", order.symbol);
 }
 
@@ -38,10 +91,545 @@ fn event_fill() {
    // THEN - we get the data we expect
    match event {
       OrderEvent::Fill { order, price, qty, timestamp: _ } => {
-         assert_eq!(100, qty);
+         assert_eq!(100.0, qty);
          assert_eq!(179.08, price);
          validate_order(order);
       },
       _ => panic!("Expected a fill order event")
    }
-}
\ No newline at end of file
+}
+#[test]
+fn event_partial_fill_fractional() {
+   //! Ensure that we can parse fractional partial fill events
+
+   // GIVEN - valid data for a fractional 'partial_fill' event
+   let data = build_event("partial_fill");
+
+   // WHEN - we deserialize it
+   let event = serde_json::from_str::<OrderEvent>(&data).unwrap();
+
+   // THEN - we get the fractional quantity
+   match event {
+      OrderEvent::PartialFill { qty, .. } => assert_eq!(2.5, qty),
+      _ => panic!("Expected a partial fill order event")
+   }
+}
+
+#[test]
+fn reconnect_after_drop() {
+   //! Ensure that the streamer reconnects, authenticates and listens again when the connection drops
+
+   block_on(async {
+      // GIVEN - a stream that drops the first connection after a fill
+      let url = start_server(|connection, mut socket| async move {
+         assert!(set_up(&mut socket).await.contains(&"trade_updates".to_string()));
+
+         socket.send(build_message("trade_updates", &build_event("fill"))).await.unwrap();
+         if connection > 0 { while socket.next().await.is_some() {} }
+      }).await;
+      let alpaca = common::build_alpaca_streaming(&url).await;
+
+      // WHEN - we stream the events
+      let streamer = Streamer::new(&alpaca).reconnect_policy(RetryPolicy::new(3).base_delay(Duration::from_millis(10)));
+      let messages = streamer.start().await.unwrap().take(3).collect::<Vec<_>>().await;
+
+      // THEN - we get the fill from each connection, with a marker in between
+      match &messages[..] {
+         [Ok(StreamMessage::Order(OrderEvent::Fill { .. })), Ok(StreamMessage::Reconnected), Ok(StreamMessage::Order(OrderEvent::Fill { .. }))] => {},
+         _ => panic!("Expected fill, reconnected, fill - got {:?}", messages)
+      }
+   });
+}
+
+#[test]
+fn bad_and_unknown_frames() {
+   //! Ensure that frames we can't read come through the stream instead of ending it
+
+   block_on(async {
+      // GIVEN - a stream sending garbage, then a new kind of message, then a fill
+      let url = start_server(|_, mut socket| async move {
+         set_up(&mut socket).await;
+         socket.send(Message::Text("not json".to_string())).await.unwrap();
+         socket.send(build_message("new_updates", r#"{"foo":"bar"}"#)).await.unwrap();
+         socket.send(build_message("trade_updates", &build_event("fill"))).await.unwrap();
+         while socket.next().await.is_some() {}
+      }).await;
+      let alpaca = common::build_alpaca_streaming(&url).await;
+
+      // WHEN - we stream the events
+      let streamer = Streamer::new(&alpaca);
+      let messages = streamer.start().await.unwrap().take(3).collect::<Vec<_>>().await;
+
+      // THEN - we get an error, the unknown message and then the fill
+      assert!(format!("{:?}", messages[0]).contains("InternalJSON"));
+      match &messages[1..] {
+         [Ok(StreamMessage::Unknown(value)), Ok(StreamMessage::Order(OrderEvent::Fill { .. }))] => assert_eq!("new_updates", value["stream"]),
+         _ => panic!("Expected unknown, fill - got {:?}", &messages[1..])
+      }
+   });
+}
+
+#[test]
+fn malformed_order_event() {
+   //! Ensure that an order event we can't read is an error rather than being passed off as unknown
+
+   block_on(async {
+      // GIVEN - a stream sending a fill without its price, a new type of order event, then a fill
+      let url = start_server(|_, mut socket| async move {
+         set_up(&mut socket).await;
+         let fill = build_event("fill").replace(r#""price": "179.08","#, "");
+         socket.send(build_message("trade_updates", &fill)).await.unwrap();
+         socket.send(build_message("trade_updates", r#"{"event":"held","order":{}}"#)).await.unwrap();
+         socket.send(build_message("trade_updates", &build_event("fill"))).await.unwrap();
+         while socket.next().await.is_some() {}
+      }).await;
+      let alpaca = common::build_alpaca_streaming(&url).await;
+
+      // WHEN - we stream the events
+      let streamer = Streamer::new(&alpaca);
+      let messages = streamer.start().await.unwrap().take(3).collect::<Vec<_>>().await;
+
+      // THEN - we get an error, the unknown event and then the fill
+      match &messages[0] {
+         Err(e) => assert_eq!(ErrorKind::Internal, e.kind()),
+         message => panic!("Expected an error - got {:?}", message)
+      }
+      match &messages[1..] {
+         [Ok(StreamMessage::Unknown(value)), Ok(StreamMessage::Order(OrderEvent::Fill { .. }))] => assert_eq!("held", value["data"]["event"]),
+         _ => panic!("Expected unknown, fill - got {:?}", &messages[1..])
+      }
+   });
+}
+
+#[test]
+#[should_panic(expected = "StreamingFailed")]
+fn start_no_server() {
+   //! Ensure that we fail gracefully when the stream can't be reached
+
+   block_on(async {
+      // GIVEN - nothing listening for the stream
+      let alpaca = common::build_alpaca_streaming("ws://127.0.0.1:1/stream").await;
+
+      // WHEN - we start streaming
+      let streamer = Streamer::new(&alpaca);
+      let _stream = streamer.start().await.unwrap();
+
+      // THEN - we get an error
+   });
+}
+
+#[test]
+fn start_handshake_timeout() {
+   //! Ensure that a connection that never completes its handshake times out
+
+   block_on(async {
+      // GIVEN - a server that takes the connection but never answers
+      let mut listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+      let url = format!("ws://{}/stream", listener.local_addr().unwrap());
+      tokio::spawn(async move {
+         let (_socket, _) = listener.accept().await.unwrap();
+         tokio::time::delay_for(Duration::from_secs(30)).await;
+      });
+      let alpaca = common::build_alpaca_streaming(&url).await;
+
+      // WHEN - we start streaming
+      let started = Instant::now();
+      let result = Streamer::new(&alpaca).connect_timeout(Duration::from_millis(100)).start().await;
+
+      // THEN - we give up rather than waiting forever
+      match result {
+         Err(e) => assert_eq!(ErrorKind::Streaming, e.kind()),
+         Ok(_) => panic!("Expected the connection to time out")
+      }
+      assert!(started.elapsed() < Duration::from_secs(5));
+   });
+}
+
+#[test]
+#[should_panic(expected = "InvalidCredentials")]
+fn start_unauthorized() {
+   //! Ensure that refused credentials are reported rather than the stream going quiet
+
+   block_on(async {
+      // GIVEN - a stream that refuses our credentials
+      let url = start_server(|_, mut socket| async move {
+         socket.next().await;
+         socket.send(build_message("authorization", r#"{"action":"authenticate","status":"unauthorized"}"#)).await.unwrap();
+         while socket.next().await.is_some() {}
+      }).await;
+      let alpaca = common::build_alpaca_streaming(&url).await;
+
+      // WHEN - we start streaming
+      let streamer = Streamer::new(&alpaca);
+      let _stream = streamer.start().await.unwrap();
+
+      // THEN - we get an error
+   });
+}
+
+#[test]
+#[should_panic(expected = "StreamSetup")]
+fn start_unexpected_answer() {
+   //! Ensure that an answer other than a refusal isn't mistaken for bad credentials
+
+   block_on(async {
+      // GIVEN - a stream that answers our credentials with a listening message
+      let url = start_server(|_, mut socket| async move {
+         socket.next().await;
+         socket.send(build_message("listening", r#"{"streams":[]}"#)).await.unwrap();
+         while socket.next().await.is_some() {}
+      }).await;
+      let alpaca = common::build_alpaca_streaming(&url).await;
+
+      // WHEN - we start streaming
+      let streamer = Streamer::new(&alpaca);
+      let _stream = streamer.start().await.unwrap();
+
+      // THEN - we get a setup error
+   });
+}
+
+#[test]
+#[should_panic(expected = "StreamSetup")]
+fn start_not_listening() {
+   //! Ensure that we find out when Alpaca doesn't listen on the streams we asked for
+
+   block_on(async {
+      // GIVEN - a stream that only listens on account updates
+      let url = start_server(|_, mut socket| async move {
+         socket.next().await;
+         socket.send(build_message("authorization", r#"{"action":"authenticate","status":"authorized"}"#)).await.unwrap();
+         socket.next().await;
+         socket.send(build_message("listening", r#"{"streams":["account_updates"]}"#)).await.unwrap();
+         while socket.next().await.is_some() {}
+      }).await;
+      let alpaca = common::build_alpaca_streaming(&url).await;
+
+      // WHEN - we start streaming
+      let streamer = Streamer::new(&alpaca);
+      let _stream = streamer.start().await.unwrap();
+
+      // THEN - we get an error
+   });
+}
+
+#[test]
+fn subscribe_while_running() {
+   //! Ensure that the streams picked are listened on, and that more can be added while running
+
+   block_on(async {
+      // GIVEN - a stream that sends a fill once we listen on account updates too
+      let url = start_server(|_, mut socket| async move {
+         assert_eq!(vec!["trade_updates".to_string()], set_up(&mut socket).await);
+
+         let listen = socket.next().await.unwrap().unwrap();
+         assert!(listen.to_string().contains("account_updates"));
+         socket.send(build_message("listening", r#"{"streams":["trade_updates","account_updates"]}"#)).await.unwrap();
+         socket.send(build_message("trade_updates", &build_event("fill"))).await.unwrap();
+         while socket.next().await.is_some() {}
+      }).await;
+      let alpaca = common::build_alpaca_streaming(&url).await;
+
+      // WHEN - we only stream orders, then subscribe to the account
+      let streamer = Streamer::new(&alpaca).streams(&[StreamKind::Order]);
+      let handle = streamer.handle();
+      let events = streamer.start().await.unwrap();
+      handle.subscribe(StreamKind::Account).unwrap();
+      let messages = events.take(2).collect::<Vec<_>>().await;
+
+      // THEN - Alpaca listens on both, and the events keep coming
+      assert_eq!(vec![StreamKind::Order, StreamKind::Account], handle.streams());
+      match &messages[..] {
+         [Ok(StreamMessage::Listening(listening)), Ok(StreamMessage::Order(OrderEvent::Fill { .. }))] => assert_eq!(2, listening.streams.len()),
+         _ => panic!("Expected listening, fill - got {:?}", messages)
+      }
+   });
+}
+
+#[test]
+fn stop_closes_connection() {
+   //! Ensure that stopping closes the connection cleanly and ends the stream of events
+
+   block_on(async {
+      // GIVEN - a stream that notes when it is closed
+      let closed = Arc::new(AtomicBool::new(false));
+      let server_closed = closed.clone();
+      let url = start_server(move |_, mut socket| {
+         let closed = server_closed.clone();
+         async move {
+            set_up(&mut socket).await;
+            while let Some(Ok(msg)) = socket.next().await {
+               if msg.is_close() { closed.store(true, Ordering::SeqCst); }
+            }
+         }
+      }).await;
+      let alpaca = common::build_alpaca_streaming(&url).await;
+      let streamer = Streamer::new(&alpaca);
+      let events = streamer.start().await.unwrap();
+
+      // WHEN - we stop the streamer
+      events.stop().await;
+
+      // THEN - the connection was closed and there are no more events
+      assert!(closed.load(Ordering::SeqCst));
+      assert_eq!(0, events.collect::<Vec<_>>().await.len());
+   });
+}
+
+#[test]
+fn stream_in_spawned_task() {
+   //! Ensure that a streamer can run in its own task and be looked after through a handle
+
+   block_on(async {
+      // GIVEN - a stream that sends a fill
+      let url = start_server(|_, mut socket| async move {
+         set_up(&mut socket).await;
+         socket.send(build_message("trade_updates", &build_event("fill"))).await.unwrap();
+         while socket.next().await.is_some() {}
+      }).await;
+      let alpaca = common::build_alpaca_streaming(&url).await;
+      let streamer = Streamer::new(&alpaca);
+      let handle = streamer.handle();
+      assert_eq!(StreamState::NotStarted, handle.state());
+
+      // WHEN - the streamer runs in another task until we stop it
+      let (first_tx, first_rx) = oneshot::channel();
+      let events = tokio::spawn(async move {
+         let mut events = streamer.start().await.unwrap();
+         first_tx.send(events.next().await).unwrap();
+         events.collect::<Vec<_>>().await
+      });
+      let first = first_rx.await.unwrap();
+      assert_eq!(StreamState::Connected, handle.state());
+      handle.stop().await;
+
+      // THEN - the task got the fill and finished
+      assert_eq!(StreamState::Stopped, handle.state());
+      assert_eq!(0, events.await.unwrap().len());
+      match first {
+         Some(Ok(StreamMessage::Order(OrderEvent::Fill { .. }))) => {},
+         message => panic!("Expected fill - got {:?}", message)
+      }
+   });
+}
+
+#[test]
+fn hub_fans_out_to_subscribers() {
+   //! Ensure that a hub passes each subscriber the events it wants and applies its lag policy
+
+   block_on(async {
+      // GIVEN - a stream that sends a fill and a partial fill, then drops for good
+      let url = start_server(|connection, mut socket| async move {
+         if connection > 0 { return; }
+         set_up(&mut socket).await;
+         socket.send(build_message("trade_updates", &build_event("fill"))).await.unwrap();
+         socket.send(build_message("trade_updates", &build_event("partial_fill"))).await.unwrap();
+      }).await;
+      let alpaca = common::build_alpaca_streaming(&url).await;
+      let streamer = Streamer::new(&alpaca).reconnect_policy(RetryPolicy::none());
+
+      // WHEN - several subscribers share the stream through a hub
+      let hub = StreamHub::new(streamer.start().await.unwrap());
+      let fills = hub.subscribe(EventFilter::new().order_event(OrderEventType::Fill), 10, LagPolicy::Error);
+      let ours = hub.subscribe(EventFilter::new().client_order_id_prefix("904837e3-"), 10, LagPolicy::Error);
+      let microsoft = hub.subscribe(EventFilter::new().symbol("MSFT"), 10, LagPolicy::Error);
+      let dropping = hub.subscribe(EventFilter::new(), 1, LagPolicy::DropOldest);
+      let lagging = hub.subscribe(EventFilter::new(), 1, LagPolicy::Error);
+
+      // THEN - each gets what it asked for, and the error when the stream is lost
+      let fills = fills.collect::<Vec<_>>().await;
+      match &fills[..] {
+         [Ok(fill), Err(_)] if matches!(**fill, StreamMessage::Order(OrderEvent::Fill { .. })) => {},
+         _ => panic!("Expected fill, error - got {:?}", fills)
+      }
+      assert_eq!(3, ours.collect::<Vec<_>>().await.len());
+      match &microsoft.collect::<Vec<_>>().await[..] {
+         [Err(e)] => assert_eq!(ErrorKind::Streaming, e.kind()),
+         messages => panic!("Expected error - got {:?}", messages)
+      }
+
+      let mut dropping = dropping;
+      assert!(dropping.next().await.unwrap().is_err());
+      assert!(dropping.next().await.is_none());
+      assert_eq!(LagMetrics { delivered: 1, dropped: 2, queued: 0, max_queued: 1 }, dropping.metrics());
+
+      let lagging = lagging.collect::<Vec<_>>().await;
+      match &lagging[..] {
+         [Ok(_), Err(e)] => assert_eq!(ErrorKind::Lagged, e.kind()),
+         _ => panic!("Expected fill, lagged - got {:?}", lagging)
+      }
+   });
+}
+
+#[test]
+fn pings_answered_and_sent() {
+   //! Ensure that the streamer echoes Alpaca's pings and sends its own
+
+   block_on(async {
+      // GIVEN - a stream that sends a fill once its ping is echoed and it has been pinged
+      let url = start_server(|_, mut socket| async move {
+         set_up(&mut socket).await;
+
+         socket.send(Message::Ping(b"heartbeat".to_vec())).await.unwrap();
+         assert_eq!(Message::Pong(b"heartbeat".to_vec()), socket.next().await.unwrap().unwrap());
+         assert!(socket.next().await.unwrap().unwrap().is_ping());
+         socket.send(build_message("trade_updates", &build_event("fill"))).await.unwrap();
+         while socket.next().await.is_some() {}
+      }).await;
+      let alpaca = common::build_alpaca_streaming(&url).await;
+
+      // WHEN - we stream the events, pinging often
+      let streamer = Streamer::new(&alpaca).ping_interval(Some(Duration::from_millis(50)));
+      let messages = streamer.start().await.unwrap().take(1).collect::<Vec<_>>().await;
+
+      // THEN - we get the fill
+      match &messages[..] {
+         [Ok(StreamMessage::Order(OrderEvent::Fill { .. }))] => {},
+         _ => panic!("Expected fill - got {:?}", messages)
+      }
+   });
+}
+
+#[test]
+fn reconnect_when_idle() {
+   //! Ensure that a connection that goes quiet is treated as dead and reconnected
+
+   block_on(async {
+      // GIVEN - a stream where the first connection goes quiet and the second sends a fill
+      let url = start_server(|connection, mut socket| async move {
+         set_up(&mut socket).await;
+
+         if connection > 0 { socket.send(build_message("trade_updates", &build_event("fill"))).await.unwrap(); }
+         while socket.next().await.is_some() {}
+      }).await;
+      let alpaca = common::build_alpaca_streaming(&url).await;
+
+      // WHEN - we stream the events without pings, and only wait a moment for each connection
+      let streamer = Streamer::new(&alpaca)
+         .ping_interval(None)
+         .idle_timeout(Some(Duration::from_millis(100)))
+         .reconnect_policy(RetryPolicy::none());
+      let messages = streamer.start().await.unwrap().take(2).collect::<Vec<_>>().await;
+
+      // THEN - the streamer reconnects and the events keep coming
+      match &messages[..] {
+         [Ok(StreamMessage::Reconnected), Ok(StreamMessage::Order(OrderEvent::Fill { .. }))] => {},
+         _ => panic!("Expected reconnected, fill - got {:?}", messages)
+      }
+   });
+}
+
+#[test]
+fn replay_at_original_speed() {
+   //! Ensure that a recorded session replays the same events, with the gaps they were recorded with
+
+   block_on(async {
+      // GIVEN - a recording of a new order, and its fill 200ms later
+      let replayer = Replayer::open("tests/streaming_data/session.jsonl").unwrap();
+
+      // WHEN - we replay it at its original speed
+      let started = Instant::now();
+      let messages = replayer.speed(ReplaySpeed::Original).start().collect::<Vec<_>>().await;
+
+      // THEN - we get the order events, as slowly as they came in
+      assert!(started.elapsed() >= Duration::from_millis(200));
+      match &messages[..] {
+         [Ok(StreamMessage::Order(OrderEvent::New { .. })), Ok(StreamMessage::Order(OrderEvent::Fill { order, .. }))] => assert_eq!("AAPL", order.symbol),
+         _ => panic!("Expected new, fill - got {:?}", messages)
+      }
+   });
+}
+
+#[test]
+fn record_and_replay() {
+   //! Ensure that the frames recorded from a stream replay as the same events
+
+   block_on(async {
+      // GIVEN - a stream that sends a fill, recorded to a file
+      let url = start_server(|_, mut socket| async move {
+         set_up(&mut socket).await;
+         socket.send(build_message("trade_updates", &build_event("fill"))).await.unwrap();
+         while socket.next().await.is_some() {}
+      }).await;
+      let alpaca = common::build_alpaca_streaming(&url).await;
+      let path = std::env::temp_dir().join(format!("alpaca-finance-record-{}.jsonl", std::process::id()));
+      let path = path.to_str().unwrap();
+
+      // WHEN - we stream the fill, then replay the recording as fast as possible
+      let streamer = Streamer::new(&alpaca).record(Recorder::create(path).unwrap());
+      let mut events = streamer.start().await.unwrap();
+      let streamed = vec![events.next().await.unwrap()];
+      events.stop().await;
+      let replayed = Replayer::open(path).unwrap().start().collect::<Vec<_>>().await;
+      std::fs::remove_file(path).unwrap();
+
+      // THEN - both have the fill
+      for messages in &[streamed, replayed] {
+         match &messages[..] {
+            [Ok(StreamMessage::Order(OrderEvent::Fill { .. }))] => {},
+            _ => panic!("Expected fill - got {:?}", messages)
+         }
+      }
+   });
+}
+
+#[test]
+fn hub_holds_events_for_first_subscriber() {
+   //! Ensure that events arriving before anyone subscribes to a hub are kept, and that subscribing after
+   //! the stream has ended gives an ended stream
+
+   block_on(async {
+      // GIVEN - a stream that sends a fill, then drops for good
+      let url = start_server(|connection, mut socket| async move {
+         if connection > 0 { return; }
+         set_up(&mut socket).await;
+         socket.send(build_message("trade_updates", &build_event("fill"))).await.unwrap();
+      }).await;
+      let alpaca = common::build_alpaca_streaming(&url).await;
+      let streamer = Streamer::new(&alpaca).reconnect_policy(RetryPolicy::none());
+      let hub = StreamHub::new(streamer.start().await.unwrap());
+
+      // WHEN - we only subscribe once the stream has had time to end, and then again after that
+      tokio::time::delay_for(Duration::from_millis(200)).await;
+      let first = hub.subscribe(EventFilter::new(), 10, LagPolicy::Error).collect::<Vec<_>>().await;
+      let late = hub.subscribe(EventFilter::new(), 10, LagPolicy::Error).collect::<Vec<_>>().await;
+
+      // THEN - the first subscriber gets the whole stream, and the late one gets nothing
+      match &first[..] {
+         [Ok(fill), Err(_)] if matches!(**fill, StreamMessage::Order(OrderEvent::Fill { .. })) => {},
+         _ => panic!("Expected fill, error - got {:?}", first)
+      }
+      assert_eq!(0, late.len());
+   });
+}
+
+#[test]
+fn hub_dropped_stops_streamer() {
+   //! Ensure that the streamer stops once a hub and all of its subscribers are gone
+
+   block_on(async {
+      // GIVEN - a hub with a subscriber
+      let url = start_server(|_, mut socket| async move {
+         set_up(&mut socket).await;
+         while socket.next().await.is_some() {}
+      }).await;
+      let alpaca = common::build_alpaca_streaming(&url).await;
+      let streamer = Streamer::new(&alpaca);
+      let handle = streamer.handle();
+      let hub = StreamHub::new(streamer.start().await.unwrap());
+      let subscriber = hub.subscribe(EventFilter::new(), 10, LagPolicy::Error);
+
+      // WHEN - the hub is dropped, then the subscriber
+      drop(hub);
+      tokio::time::delay_for(Duration::from_millis(50)).await;
+      let running = handle.state();
+      drop(subscriber);
+      let stopped = tokio::time::timeout(Duration::from_secs(5), async {
+         while handle.state() != StreamState::Stopped { tokio::time::delay_for(Duration::from_millis(10)).await; }
+      }).await;
+
+      // THEN - the streamer kept going while the subscriber was around, and stopped after
+      assert_eq!(StreamState::Connected, running);
+      assert!(stopped.is_ok());
+   });
+}